@@ -0,0 +1,4 @@
+ALTER TABLE users
+  DROP COLUMN notify_time,
+  DROP COLUMN timezone,
+  DROP COLUMN last_notified;
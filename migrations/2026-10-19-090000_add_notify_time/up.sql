@@ -0,0 +1,4 @@
+ALTER TABLE users
+  ADD COLUMN notify_time SMALLINT,
+  ADD COLUMN timezone VARCHAR NOT NULL DEFAULT 'Europe/Kiev',
+  ADD COLUMN last_notified DATE;
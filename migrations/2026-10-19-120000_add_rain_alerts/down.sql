@@ -0,0 +1,5 @@
+ALTER TABLE users
+  DROP COLUMN latitude,
+  DROP COLUMN longitude,
+  DROP COLUMN rain_alerts,
+  DROP COLUMN last_rain_alert;
@@ -0,0 +1,5 @@
+ALTER TABLE users
+  ADD COLUMN latitude DOUBLE PRECISION,
+  ADD COLUMN longitude DOUBLE PRECISION,
+  ADD COLUMN rain_alerts BOOLEAN NOT NULL DEFAULT 'f',
+  ADD COLUMN last_rain_alert TIMESTAMP;
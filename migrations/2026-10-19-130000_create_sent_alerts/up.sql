@@ -0,0 +1,8 @@
+CREATE TABLE sent_alerts (
+  id SERIAL PRIMARY KEY,
+  viber_id VARCHAR NOT NULL,
+  alert_id VARCHAR NOT NULL,
+  expires TIMESTAMP NOT NULL,
+  sent_at TIMESTAMP NOT NULL DEFAULT now(),
+  UNIQUE (viber_id, alert_id, expires)
+)
@@ -0,0 +1,8 @@
+CREATE TABLE alert_rules (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  field VARCHAR NOT NULL,
+  op VARCHAR NOT NULL,
+  value DOUBLE PRECISION NOT NULL,
+  last_triggered DATE
+)
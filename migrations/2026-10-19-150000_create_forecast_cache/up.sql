@@ -0,0 +1,8 @@
+CREATE TABLE forecast_cache (
+  lat_key INTEGER NOT NULL,
+  lon_key INTEGER NOT NULL,
+  lang VARCHAR NOT NULL,
+  data TEXT NOT NULL,
+  fetched_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (lat_key, lon_key, lang)
+)
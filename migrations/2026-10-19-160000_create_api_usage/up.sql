@@ -0,0 +1,16 @@
+CREATE TABLE api_calls (
+  id SERIAL PRIMARY KEY,
+  provider VARCHAR NOT NULL,
+  endpoint VARCHAR NOT NULL,
+  latency_ms INTEGER NOT NULL,
+  success BOOLEAN NOT NULL,
+  called_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE TABLE api_usage (
+  provider VARCHAR NOT NULL,
+  day DATE NOT NULL,
+  calls INTEGER NOT NULL DEFAULT 0,
+  warned BOOLEAN NOT NULL DEFAULT 'f',
+  PRIMARY KEY (provider, day)
+);
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN forecast_fields VARCHAR;
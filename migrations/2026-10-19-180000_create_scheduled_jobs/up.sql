@@ -0,0 +1,7 @@
+CREATE TABLE scheduled_jobs (
+  name VARCHAR PRIMARY KEY,
+  last_run TIMESTAMP,
+  last_success TIMESTAMP,
+  next_due TIMESTAMP,
+  failures INTEGER NOT NULL DEFAULT 0
+)
@@ -0,0 +1,3 @@
+ALTER TABLE scheduled_jobs DROP COLUMN paused;
+
+DROP TABLE job_runs;
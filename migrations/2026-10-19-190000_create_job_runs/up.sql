@@ -0,0 +1,13 @@
+CREATE TABLE job_runs (
+  id SERIAL PRIMARY KEY,
+  job VARCHAR NOT NULL,
+  started TIMESTAMP NOT NULL,
+  finished TIMESTAMP NOT NULL,
+  success BOOLEAN NOT NULL,
+  error TEXT,
+  messages_sent INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX job_runs_job_started ON job_runs (job, started);
+
+ALTER TABLE scheduled_jobs ADD COLUMN paused BOOLEAN NOT NULL DEFAULT 'f';
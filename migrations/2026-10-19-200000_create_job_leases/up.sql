@@ -0,0 +1,5 @@
+CREATE TABLE job_leases (
+  name VARCHAR PRIMARY KEY,
+  holder VARCHAR NOT NULL,
+  expires TIMESTAMP NOT NULL
+);
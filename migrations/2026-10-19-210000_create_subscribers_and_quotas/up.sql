@@ -0,0 +1,13 @@
+CREATE TABLE subscribers (
+  viber_id VARCHAR PRIMARY KEY,
+  name VARCHAR NOT NULL,
+  avatar VARCHAR,
+  role VARCHAR NOT NULL
+);
+
+CREATE TABLE quotas (
+  viber_id VARCHAR PRIMARY KEY,
+  day DATE NOT NULL,
+  weather_count INTEGER NOT NULL,
+  btc_count INTEGER NOT NULL
+);
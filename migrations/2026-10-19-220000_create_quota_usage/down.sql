@@ -0,0 +1,8 @@
+DROP TABLE quota_usage;
+
+CREATE TABLE quotas (
+  viber_id VARCHAR PRIMARY KEY,
+  day DATE NOT NULL,
+  weather_count INTEGER NOT NULL,
+  btc_count INTEGER NOT NULL
+);
@@ -0,0 +1,10 @@
+DROP TABLE quotas;
+
+CREATE TABLE quota_usage (
+  id SERIAL PRIMARY KEY,
+  viber_id VARCHAR NOT NULL,
+  command VARCHAR NOT NULL,
+  used_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX quota_usage_user_command ON quota_usage (viber_id, command, used_at);
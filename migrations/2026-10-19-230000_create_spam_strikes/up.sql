@@ -0,0 +1,6 @@
+CREATE TABLE spam_strikes (
+  viber_id VARCHAR PRIMARY KEY,
+  strikes INTEGER NOT NULL,
+  last_strike TIMESTAMP NOT NULL,
+  blocked_until TIMESTAMP
+);
@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN link_code_expires;
+ALTER TABLE users DROP COLUMN link_code;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN link_code VARCHAR UNIQUE;
+ALTER TABLE users ADD COLUMN link_code_expires TIMESTAMP;
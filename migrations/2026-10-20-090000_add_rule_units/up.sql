@@ -0,0 +1,3 @@
+ALTER TABLE alert_rules ADD COLUMN units VARCHAR NOT NULL DEFAULT 'uk';
+UPDATE alert_rules SET units = users.units FROM users WHERE users.id = alert_rules.user_id AND users.units IS NOT NULL;
+ALTER TABLE alert_rules ALTER COLUMN units DROP DEFAULT;
@@ -2,10 +2,13 @@ use super::*;
 use actix_web::middleware::identity::RequestIdentity;
 use actix_web::*;
 use chrono::TimeZone;
-use common::messages::WorkerUnit;
+use common::messages::{ForecastKind, WorkerUnit};
 use common::*;
 use futures::prelude::*;
+use i18n::Lang;
+use ratelimit::Verdict;
 use std::collections::HashMap;
+use units::UnitSystem;
 use viber::messages::CallbackMessage;
 use viber::raw;
 use workers::WebWorker;
@@ -13,8 +16,15 @@ use workers::WebWorker;
 use api::auth::GoogleProfile;
 use oauth2::prelude::*;
 use oauth2::{AuthorizationCode, CsrfToken};
+use workers::db::AddRule;
+use workers::db::AllSubscribers;
+use workers::db::DeleteRule;
+use workers::db::LinkCode;
 use workers::db::RegisterUser;
+use workers::db::RulesByEmail;
+use workers::db::UpdateSettings;
 use workers::db::UserByEmail;
+use workers::jobs::{PauseJob, RecentRuns, RunJob};
 
 pub mod auth;
 
@@ -23,9 +33,22 @@ pub fn list(
 ) -> Result<HttpResponse, Error> {
     let mut ctx = tera::Context::new();
     ctx.insert("text", &"Welcome!".to_owned());
-    let ts = state.last_text_broadcast.read().unwrap().last_success;
+    let ts = state
+        .jobs
+        .read()
+        .unwrap()
+        .last_success("forecast_broadcast");
     ctx.insert("last_broadcast", &chrono::Utc.timestamp(ts, 0).to_rfc2822());
-    ctx.insert("members", &state.subscribers);
+    let addr = {
+        let mut addr = state.addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    let members = addr
+        .send(AllSubscribers)
+        .wait()
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .unwrap_or_default();
+    ctx.insert("members", &members);
     let html = state.template.render("index.html", &ctx).map_err(|e| {
         error!("Template error! {:?}", e);
         error::ErrorInternalServerError("Template error")
@@ -34,7 +57,32 @@ pub fn list(
 }
 
 pub fn verify(req: &HttpRequest<AppStateType>) -> Result<HttpResponse, Error> {
-    Ok(HttpResponse::Ok().content_type("text/html").body(""))
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let state = req.state();
+    let addr = {
+        let mut addr = state.addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    let code = addr
+        .send(LinkCode(email))
+        .wait()
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .map_err(|e| {
+            error!("Failed to make a link code. {:?}", e);
+            error::ErrorInternalServerError("Failed to make a link code")
+        })?;
+    let mut ctx = tera::Context::new();
+    ctx.insert("code", &code);
+    ctx.insert("brand", state.config.brand_name());
+    ctx.insert("minutes", &workers::db::LINK_CODE_MINUTES);
+    let html = state.template.render("verify.html", &ctx).map_err(|e| {
+        error!("Template error! {:?}", e);
+        error::ErrorInternalServerError("Template error")
+    })?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
 }
 
 pub fn viber_webhook(
@@ -46,7 +94,7 @@ pub fn viber_webhook(
         temp.get_mut().as_ref().unwrap().clone()
     };
     let key = req.state().config.viber_api_key.clone().unwrap();
-    let kb = Some(get_default_keyboard());
+    let state = state.clone();
 
     req.payload()
         .concat2()
@@ -59,21 +107,64 @@ pub fn viber_webhook(
                     match msg.event.as_ref() {
                         "conversation_started" => {
                             let user = msg.user.as_ref().unwrap();
-                            raw::send_text_message(
-                                "Welcome to Kiev Alerts",
-                                &user.id.to_string(),
-                                &key,
-                                kb,
-                            )
-                            .wait()
-                            .map_err(|e| {
-                                error!("Could not send welcome message.");
-                            });
+                            let lang = Lang::from_code(&user.language).unwrap_or_default();
+                            let mut ctx = tera::Context::new();
+                            ctx.insert("brand", state.config.brand_name());
+                            ctx.insert("city", state.config.default_city());
+                            match state.messages.render(lang, "welcome", &ctx) {
+                                Ok(text) => {
+                                    raw::send_text_message(
+                                        &text,
+                                        &user.id.to_string(),
+                                        &key,
+                                        Some(get_default_keyboard(&state.i18n, lang)),
+                                    )
+                                    .wait()
+                                    .map_err(|e| {
+                                        error!("Could not send welcome message.");
+                                    });
+                                }
+                                Err(e) => error!("Could not format welcome message. {:?}", e),
+                            }
                         }
 
                         "message" => {
-                            let cmd = handle_user_message(&msg);
-                            addr.do_send(cmd);
+                            let sender = msg.sender.as_ref().unwrap();
+                            let user_id = sender.id.as_ref().unwrap().to_string();
+                            let lang = sender
+                                .language
+                                .as_ref()
+                                .and_then(|l| Lang::from_code(l))
+                                .unwrap_or_default();
+                            let notice = match state.inbound.check(&user_id) {
+                                Verdict::Allow => {
+                                    addr.do_send(WorkerUnit::RegisterSender {
+                                        user_id: user_id.clone(),
+                                        language: sender.language.as_ref().map(|l| l.to_string()),
+                                    });
+                                    let cmd = handle_user_message(&msg);
+                                    addr.do_send(cmd);
+                                    None
+                                }
+                                Verdict::Cooldown(seconds) => Some(state.i18n.format(
+                                    lang,
+                                    "rate_limited",
+                                    &[("seconds", seconds.to_string().as_str())],
+                                )),
+                                Verdict::Blocked(minutes) => Some(state.i18n.format(
+                                    lang,
+                                    "rate_blocked",
+                                    &[("minutes", minutes.to_string().as_str())],
+                                )),
+                                Verdict::Drop => None,
+                            };
+                            if let Some(text) = notice {
+                                if let Err(e) =
+                                    raw::send_text_message(&text, &user_id, &key, None).wait()
+                                {
+                                    error!("Could not send the rate limit notice. {:?}", e);
+                                }
+                            }
                         }
                         _ => {}
                     }
@@ -104,12 +195,29 @@ fn handle_user_message(msg: &CallbackMessage) -> WorkerUnit {
             "bitcoin" => WorkerUnit::BTCPrice {
                 user_id: user.to_string(),
             },
-            "forecast_kiev_tomorrow" => WorkerUnit::TomorrowForecast {
+            // the old action is still sent by keyboards of earlier messages
+            "forecast_tomorrow" | "forecast_kiev_tomorrow" => WorkerUnit::TomorrowForecast {
+                user_id: user.to_string(),
+            },
+            "now" => WorkerUnit::Forecast {
+                user_id: user.to_string(),
+                kind: ForecastKind::Now,
+            },
+            "today" => WorkerUnit::Forecast {
                 user_id: user.to_string(),
+                kind: ForecastKind::Today,
             },
-            _ => WorkerUnit::UnknownCommand {
+            "hourly" => WorkerUnit::Forecast {
                 user_id: user.to_string(),
+                kind: ForecastKind::Hourly,
             },
+            "week" => WorkerUnit::Forecast {
+                user_id: user.to_string(),
+                kind: ForecastKind::Week,
+            },
+            text => parse_settings_command(user, text).unwrap_or(WorkerUnit::UnknownCommand {
+                user_id: user.to_string(),
+            }),
         },
         _ => WorkerUnit::UnknownCommand {
             user_id: user.to_string(),
@@ -118,6 +226,351 @@ fn handle_user_message(msg: &CallbackMessage) -> WorkerUnit {
     actor_message
 }
 
+fn parse_settings_command(user: &str, text: &str) -> Option<WorkerUnit> {
+    let text = text.trim();
+    match text {
+        "rain on" | "rain off" => {
+            return Some(WorkerUnit::SetRainAlerts {
+                user_id: user.to_string(),
+                enabled: text == "rain on",
+            })
+        }
+        "rules" => {
+            return Some(WorkerUnit::ListRules {
+                user_id: user.to_string(),
+            })
+        }
+        "quota" => {
+            return Some(WorkerUnit::ShowQuota {
+                user_id: user.to_string(),
+            })
+        }
+        _ => {}
+    }
+    if text.starts_with("rule del ") {
+        return text["rule del ".len()..]
+            .trim()
+            .trim_left_matches('#')
+            .parse()
+            .ok()
+            .map(|id| WorkerUnit::DeleteRule {
+                user_id: user.to_string(),
+                id,
+            });
+    }
+    if text.starts_with("rule ") {
+        return Some(WorkerUnit::AddRule {
+            user_id: user.to_string(),
+            rule: text["rule ".len()..].trim().to_owned(),
+        });
+    }
+    if text.starts_with("link ") {
+        return Some(WorkerUnit::LinkAccount {
+            user_id: user.to_string(),
+            code: text["link ".len()..].trim().to_uppercase(),
+        });
+    }
+    if text.starts_with("unblock ") {
+        return Some(WorkerUnit::Unblock {
+            user_id: user.to_string(),
+            blocked: text["unblock ".len()..].trim().to_owned(),
+        });
+    }
+    if text.starts_with("details ") {
+        return Some(WorkerUnit::SetForecastFields {
+            user_id: user.to_string(),
+            fields: text["details ".len()..].trim().to_owned(),
+        });
+    }
+    if text.starts_with("units ") {
+        return Some(WorkerUnit::SetUnits {
+            user_id: user.to_string(),
+            units: text["units ".len()..].trim().to_owned(),
+        });
+    }
+    if text.starts_with("lang ") {
+        return Some(WorkerUnit::SetLanguage {
+            user_id: user.to_string(),
+            language: text["lang ".len()..].trim().to_owned(),
+        });
+    }
+    if text.starts_with("tz ") {
+        return Some(WorkerUnit::SetTimezone {
+            user_id: user.to_string(),
+            timezone: text["tz ".len()..].trim().to_owned(),
+        });
+    }
+    let time = if text.starts_with("time ") {
+        text["time ".len()..].trim()
+    } else {
+        text
+    };
+    if time == "off" {
+        return Some(WorkerUnit::SetNotifyTime {
+            user_id: user.to_string(),
+            minutes: None,
+        });
+    }
+    scheduler::parse_time_of_day(time).map(|t| WorkerUnit::SetNotifyTime {
+        user_id: user.to_string(),
+        minutes: Some(scheduler::time_to_minutes(&t)),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    pub notify_time: Option<String>,
+    pub timezone: Option<String>,
+    pub language: Option<String>,
+    pub units: Option<String>,
+    pub forecast_fields: Option<String>,
+    pub rain_alerts: Option<String>,
+}
+
+pub fn settings(
+    (req, form): (HttpRequest<AppStateType>, Form<SettingsForm>),
+) -> Result<HttpResponse, Error> {
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let addr = {
+        let mut addr = req.state().addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    // an empty time turns the delivery off, a time that can't be read is rejected
+    let notify_time = match form.notify_time.as_ref().map(|t| t.trim()) {
+        None => return Err(error::ErrorBadRequest("The notification time is missing")),
+        Some("") => None,
+        Some(t) => match scheduler::parse_time_of_day(t) {
+            Some(t) => Some(scheduler::time_to_minutes(&t)),
+            None => {
+                return Err(error::ErrorBadRequest(format!(
+                    "Invalid notification time {:?}, expected HH:MM like 07:30",
+                    t
+                )))
+            }
+        },
+    };
+    addr.send(UpdateSettings {
+        email,
+        notify_time,
+        timezone: form.timezone.clone().filter(|tz| !tz.trim().is_empty()),
+        language: form.language.as_ref().and_then(|l| Lang::from_code(l)),
+        units: form.units.as_ref().and_then(|u| UnitSystem::from_code(u)),
+        forecast_fields: form.forecast_fields.clone(),
+        rain_alerts: form.rain_alerts.is_some(),
+    })
+    .wait()
+    .map_err(|e| error::ErrorInternalServerError(e))?
+    .map_err(|e| {
+        error!("Failed to update settings. {:?}", e);
+        error::ErrorBadRequest("Invalid settings")
+    })?;
+    Ok(HttpResponse::Found().header("location", "/api/").finish())
+}
+
+#[derive(Deserialize)]
+pub struct RuleForm {
+    pub field: String,
+    pub op: String,
+    pub value: String,
+}
+
+pub fn add_rule(
+    (req, form): (HttpRequest<AppStateType>, Form<RuleForm>),
+) -> Result<HttpResponse, Error> {
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let addr = {
+        let mut addr = req.state().addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    addr.send(AddRule {
+        email,
+        rule: format!("{} {} {}", form.field, form.op, form.value),
+    })
+    .wait()
+    .map_err(|e| error::ErrorInternalServerError(e))?
+    .map_err(|e| {
+        error!("Failed to add a rule. {:?}", e);
+        error::ErrorBadRequest("Invalid rule, or the Viber account is not linked")
+    })?;
+    Ok(HttpResponse::Found().header("location", "/api/").finish())
+}
+
+#[derive(Deserialize)]
+pub struct DeleteRuleForm {
+    pub id: i32,
+}
+
+pub fn delete_rule(
+    (req, form): (HttpRequest<AppStateType>, Form<DeleteRuleForm>),
+) -> Result<HttpResponse, Error> {
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let addr = {
+        let mut addr = req.state().addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    addr.send(DeleteRule { email, id: form.id })
+        .wait()
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .map_err(|e| {
+            error!("Failed to delete a rule. {:?}", e);
+            error::ErrorInternalServerError("Failed to delete the rule")
+        })?;
+    Ok(HttpResponse::Found().header("location", "/api/").finish())
+}
+
+// the admin can manage the scheduled jobs
+fn is_admin(state: &AppState, user: &models::User) -> bool {
+    (user.viber_id.is_some() && user.viber_id == state.config.admin_id)
+        || (user.email.is_some() && user.email == state.config.admin_email)
+}
+
+#[derive(Serialize)]
+struct JobView {
+    name: &'static str,
+    schedule: String,
+    misfire: &'static str,
+    next_run: String,
+    last_success: String,
+    failures: i32,
+    paused: bool,
+    runs: Vec<models::JobRun>,
+}
+
+impl JobView {
+    fn of(job: &scheduler::Job, runs: Vec<models::JobRun>) -> JobView {
+        let last_success = job.state.last_success;
+        JobView {
+            name: job.name,
+            schedule: job.schedule_text.clone(),
+            misfire: job.misfire.code(),
+            next_run: job
+                .next_run()
+                .map(|t| t.to_rfc2822())
+                .unwrap_or_else(|| "-".to_owned()),
+            last_success: if last_success == 0 {
+                "-".to_owned()
+            } else {
+                chrono::Utc.timestamp(last_success, 0).to_rfc2822()
+            },
+            failures: job.state.failures,
+            paused: job.state.paused,
+            runs,
+        }
+    }
+}
+
+pub fn jobs(req: &HttpRequest<AppStateType>) -> Result<HttpResponse, Error> {
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let state = req.state();
+    let addr = {
+        let mut addr = state.addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    let user = addr
+        .send(UserByEmail(email))
+        .wait()
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .map_err(|_| error::ErrorForbidden("Not allowed"))?;
+    if !is_admin(state, &user) {
+        return Err(error::ErrorForbidden("Not allowed"));
+    }
+    let names: Vec<&'static str> = state
+        .jobs
+        .read()
+        .unwrap()
+        .jobs()
+        .iter()
+        .map(|job| job.name)
+        .collect();
+    let mut runs = HashMap::new();
+    for name in names {
+        let recent = addr
+            .send(RecentRuns(name.to_owned()))
+            .wait()
+            .map_err(|e| error::ErrorInternalServerError(e))?
+            .map_err(|e| {
+                error!("Failed to read the runs of the {} job. {:?}", name, e);
+                error::ErrorInternalServerError("Failed to read the job history")
+            })?;
+        runs.insert(name, recent);
+    }
+    let views: Vec<JobView> = state
+        .jobs
+        .read()
+        .unwrap()
+        .jobs()
+        .iter()
+        .map(|job| JobView::of(job, runs.remove(job.name).unwrap_or_default()))
+        .collect();
+    let mut ctx = tera::Context::new();
+    ctx.insert("jobs", &views);
+    let html = state.template.render("jobs.html", &ctx).map_err(|e| {
+        error!("Template error! {:?}", e);
+        error::ErrorInternalServerError("Template error")
+    })?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+#[derive(Deserialize)]
+pub struct JobForm {
+    pub name: String,
+    pub action: String,
+}
+
+pub fn job_action(
+    (req, form): (HttpRequest<AppStateType>, Form<JobForm>),
+) -> Result<HttpResponse, Error> {
+    let email = match req.identity() {
+        Some(email) => email,
+        None => return Ok(HttpResponse::Found().header("location", "/api/").finish()),
+    };
+    let addr = {
+        let mut addr = req.state().addr.lock().unwrap();
+        addr.get_mut().as_ref().unwrap().clone()
+    };
+    let user = addr
+        .send(UserByEmail(email))
+        .wait()
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .map_err(|_| error::ErrorForbidden("Not allowed"))?;
+    if !is_admin(req.state(), &user) {
+        return Err(error::ErrorForbidden("Not allowed"));
+    }
+    let name = form.name.clone();
+    let result = match form.action.as_str() {
+        "run" => addr.send(RunJob(name)).wait(),
+        "pause" => addr.send(PauseJob { name, paused: true }).wait(),
+        "resume" => addr
+            .send(PauseJob {
+                name,
+                paused: false,
+            })
+            .wait(),
+        _ => return Err(error::ErrorBadRequest("Unknown action")),
+    };
+    result
+        .map_err(|e| error::ErrorInternalServerError(e))?
+        .map_err(|e| {
+            error!("Failed to {} the {} job. {:?}", form.action, form.name, e);
+            error::ErrorBadRequest("Invalid job")
+        })?;
+    Ok(HttpResponse::Found()
+        .header("location", "/api/jobs/")
+        .finish())
+}
+
 pub fn google_oauth(
     req: &HttpRequest<AppStateType>,
 ) -> Box<Future<Item = HttpResponse, Error = Error>> {
@@ -173,7 +626,7 @@ pub fn index(req: &HttpRequest<AppStateType>) -> Result<HttpResponse, Error> {
             .as_ref()
             .unwrap()
             .authorize_url(CsrfToken::new_random);
-        ctx.insert("app_name", &"Viber Alerts!".to_owned());
+        ctx.insert("app_name", state.config.brand_name());
         ctx.insert("auth_url", &authorize_url.to_string());
         let html = state.template.render("login.html", &ctx).map_err(|e| {
             error!("Template error! {:?}", e);
@@ -183,9 +636,18 @@ pub fn index(req: &HttpRequest<AppStateType>) -> Result<HttpResponse, Error> {
     } else {
         let mut ctx = tera::Context::new();
 
-        let ts = state.last_text_broadcast.read().unwrap().last_success;
+        let ts = state
+            .jobs
+            .read()
+            .unwrap()
+            .last_success("forecast_broadcast");
         ctx.insert("last_broadcast", &chrono::Utc.timestamp(ts, 0).to_rfc2822());
-        ctx.insert("members", &state.subscribers);
+        let members = addr
+            .send(AllSubscribers)
+            .wait()
+            .map_err(|e| error::ErrorInternalServerError(e))?
+            .unwrap_or_default();
+        ctx.insert("members", &members);
         let user_email = req.identity().unwrap();
 
         let result = addr.send(UserByEmail(user_email.clone())).wait();
@@ -199,7 +661,44 @@ pub fn index(req: &HttpRequest<AppStateType>) -> Result<HttpResponse, Error> {
         .unwrap();
 
         ctx.insert("email", user.email.as_ref().unwrap());
+        ctx.insert(
+            "notify_time",
+            &user
+                .notify_time
+                .map(|m| scheduler::minutes_to_time(m).format("%H:%M").to_string())
+                .unwrap_or_default(),
+        );
+        ctx.insert("timezone", &user.timezone);
+        ctx.insert("language", user.lang().code());
+        ctx.insert(
+            "languages",
+            &Lang::all().iter().map(|l| l.code()).collect::<Vec<_>>(),
+        );
+        ctx.insert("rain_alerts", &user.rain_alerts);
+        ctx.insert("has_location", &user.location().is_some());
+        ctx.insert("units", user.units().code());
+        ctx.insert(
+            "forecast_fields",
+            &workers::Prefs::of(&user).fields.join(", "),
+        );
+        ctx.insert("forecast_field_names", &workers::details::FIELDS.join(", "));
+        ctx.insert(
+            "unit_systems",
+            &UnitSystem::all()
+                .iter()
+                .map(|u| u.code())
+                .collect::<Vec<_>>(),
+        );
+        let rules = addr
+            .send(RulesByEmail(user.email.clone().unwrap()))
+            .wait()
+            .map_err(|e| error::ErrorInternalServerError(e))?
+            .unwrap_or_default();
+        ctx.insert("rules", &rules);
+        ctx.insert("rule_fields", &workers::rules::FIELDS);
+        ctx.insert("rule_operators", &workers::rules::OPERATORS);
         ctx.insert("verified", &user.viber_id.is_some());
+        ctx.insert("admin", &is_admin(state, &user));
         let html = state.template.render("index.html", &ctx).map_err(|e| {
             error!("Template error! {:?}", e);
             error::ErrorInternalServerError("Template error")
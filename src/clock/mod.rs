@@ -0,0 +1,111 @@
+use chrono::{DateTime, Duration, Utc};
+use config::Config;
+use std::sync::{Arc, Mutex};
+
+// the time can be frozen to replay the recorded forecasts
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub struct FixedClock {
+    time: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(time: DateTime<Utc>) -> FixedClock {
+        FixedClock {
+            time: Mutex::new(time),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time.lock().unwrap();
+        *time = *time + duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time.lock().unwrap()
+    }
+}
+
+pub fn from_config(config: &Config) -> Arc<Clock> {
+    match config.fixed_time {
+        Some(ref time) => Arc::new(FixedClock::new(
+            DateTime::parse_from_rfc3339(time)
+                .expect("fixed_time is not an RFC 3339 date")
+                .with_timezone(&Utc),
+        )),
+        None => Arc::new(SystemClock),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use testing::recorded;
+
+    #[test]
+    fn stands_still() {
+        let clock = FixedClock::new(recorded());
+        thread::sleep(::std::time::Duration::from_millis(10));
+        assert_eq!(clock.now(), recorded());
+        assert_eq!(clock.now(), clock.now());
+    }
+
+    #[test]
+    fn advance() {
+        let clock = FixedClock::new(recorded());
+        clock.advance(Duration::hours(5));
+        clock.advance(Duration::minutes(30));
+        assert_eq!(clock.now(), recorded() + Duration::minutes(330));
+        clock.advance(Duration::days(-1));
+        assert_eq!(clock.now(), recorded() - Duration::minutes(1110));
+    }
+
+    #[test]
+    fn set() {
+        let clock = FixedClock::new(recorded());
+        clock.advance(Duration::hours(5));
+        clock.set(recorded() + Duration::days(1));
+        assert_eq!(clock.now(), recorded() + Duration::days(1));
+        // advances from the time set
+        clock.advance(Duration::seconds(1));
+        assert_eq!(clock.now(), recorded() + Duration::seconds(86_401));
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let clock = Arc::new(FixedClock::new(recorded()));
+        let other = clock.clone();
+        thread::spawn(move || other.advance(Duration::hours(1)))
+            .join()
+            .unwrap();
+        assert_eq!(clock.now(), recorded() + Duration::hours(1));
+    }
+
+    #[test]
+    fn from_the_config() {
+        let config: Config =
+            ::serde_json::from_str(r#"{"fixed_time": "2026-10-19T09:00:00+03:00"}"#).unwrap();
+        assert_eq!(from_config(&config).now(), recorded());
+        let config: Config = ::serde_json::from_str("{}").unwrap();
+        let before = Utc::now();
+        let now = from_config(&config).now();
+        assert!(before <= now && now <= Utc::now());
+    }
+}
@@ -1,9 +1,81 @@
 use actix::Message;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForecastKind {
+    Now,
+    Today,
+    Hourly,
+    Week,
+}
+
 #[derive(Message)]
 pub enum WorkerUnit {
-    TomorrowForecast { user_id: String },
-    ImmediateTomorrowForecast { user_id: String, lat: f64, lon: f64 },
-    BTCPrice { user_id: String },
-    UnknownCommand { user_id: String },
+    TomorrowForecast {
+        user_id: String,
+    },
+    Forecast {
+        user_id: String,
+        kind: ForecastKind,
+    },
+    ImmediateTomorrowForecast {
+        user_id: String,
+        lat: f64,
+        lon: f64,
+    },
+    BTCPrice {
+        user_id: String,
+    },
+    SetNotifyTime {
+        user_id: String,
+        minutes: Option<i16>,
+    },
+    SetTimezone {
+        user_id: String,
+        timezone: String,
+    },
+    SetLanguage {
+        user_id: String,
+        language: String,
+    },
+    SetUnits {
+        user_id: String,
+        units: String,
+    },
+    SetRainAlerts {
+        user_id: String,
+        enabled: bool,
+    },
+    SetForecastFields {
+        user_id: String,
+        fields: String,
+    },
+    AddRule {
+        user_id: String,
+        rule: String,
+    },
+    ListRules {
+        user_id: String,
+    },
+    DeleteRule {
+        user_id: String,
+        id: i32,
+    },
+    LinkAccount {
+        user_id: String,
+        code: String,
+    },
+    ShowQuota {
+        user_id: String,
+    },
+    Unblock {
+        user_id: String,
+        blocked: String,
+    },
+    RegisterSender {
+        user_id: String,
+        language: Option<String>,
+    },
+    UnknownCommand {
+        user_id: String,
+    },
 }
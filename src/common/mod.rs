@@ -1,25 +1,33 @@
+use i18n::{Catalog, Lang};
 use std::borrow::Cow;
 use viber::messages::{Button, Keyboard};
 
 pub mod messages;
 
-pub fn get_default_keyboard<'a>() -> Keyboard<'a> {
+// viber keyboards are 6 columns wide, two buttons per row
+static BUTTON_COLUMNS: u8 = 3;
+
+fn reply_button<'a>(catalog: &Catalog, lang: Lang, action: &'a str, label: &str) -> Button<'a> {
+    Button {
+        action_body: Cow::from(action),
+        action_type: Cow::from("reply"),
+        text: Cow::from(catalog.get(lang, label)),
+        text_size: Cow::from("regular"),
+        columns: Some(BUTTON_COLUMNS),
+    }
+}
+
+pub fn get_default_keyboard<'a>(catalog: &Catalog, lang: Lang) -> Keyboard<'a> {
     Keyboard {
         default_height: true,
         _type: Cow::from("keyboard"),
         buttons: vec![
-            Button {
-                action_body: Cow::from("bitcoin"),
-                action_type: Cow::from("reply"),
-                text: Cow::from("Bitcoin Price"),
-                text_size: Cow::from("regular"),
-            },
-            Button {
-                action_body: Cow::from("forecast_kiev_tomorrow"),
-                action_type: Cow::from("reply"),
-                text: Cow::from("Weather For Tomorrow"),
-                text_size: Cow::from("regular"),
-            },
+            reply_button(catalog, lang, "bitcoin", "keyboard_bitcoin"),
+            reply_button(catalog, lang, "forecast_tomorrow", "keyboard_tomorrow"),
+            reply_button(catalog, lang, "now", "keyboard_now"),
+            reply_button(catalog, lang, "today", "keyboard_today"),
+            reply_button(catalog, lang, "hourly", "keyboard_hourly"),
+            reply_button(catalog, lang, "week", "keyboard_week"),
         ],
     }
 }
@@ -1,16 +1,64 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+static DEFAULT_LATITUDE: f64 = 50.4501;
+static DEFAULT_LONGITUDE: f64 = 30.5234;
+static DEFAULT_CITY: &str = "Kyiv";
+static BRAND_NAME: &str = "Kiev Alerts";
+
+#[derive(Debug, Fail)]
+#[fail(display = "Configuration error: {}", msg)]
+pub struct ConfigError {
+    pub msg: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub viber_api_key: Option<String>,
     pub admin_id: Option<String>,
+    // google account of the admin on the web pages, besides the one linked to admin_id
+    pub admin_email: Option<String>,
     pub domain_root_url: Option<String>,
-    pub dark_sky_api_key: Option<String>,
+    pub default_latitude: Option<f64>,
+    pub default_longitude: Option<f64>,
+    pub default_city: Option<String>,
+    pub brand_name: Option<String>,
+    pub weather_provider: Option<String>,
+    pub open_weather_map_api_key: Option<String>,
+    pub fixtures_dir: Option<String>,
+    // RFC 3339, e.g. to replay the fixture forecasts
+    pub fixed_time: Option<String>,
+    pub forecast_cache_ttl: Option<i64>,
+    pub forecast_cache_db: Option<bool>,
+    pub api_budgets: Option<HashMap<String, i32>>,
     pub hosting_root_url: Option<String>,
+    pub forecast_pictures: Option<bool>,
     pub database_url: Option<String>,
     pub google_client_id: Option<String>,
     pub google_client_secret: Option<String>,
     pub google_maps_api_key: Option<String>,
+    pub rain_alert_threshold: Option<f64>,
+    pub rain_alert_cooldown: Option<i64>,
+    pub rain_check_interval: Option<i64>,
+    pub alerts_check_interval: Option<i64>,
+    pub scheduler_timezone: Option<String>,
+    pub job_schedules: Option<HashMap<String, String>>,
+    pub job_misfires: Option<HashMap<String, String>>,
+    pub quota_limits: Option<HashMap<String, String>>,
+    pub quota_exempt: Option<Vec<String>>,
+    pub rate_limit_burst: Option<f64>,
+    pub rate_limit_per_minute: Option<f64>,
+    pub rate_limit_strikes: Option<i32>,
+    pub rate_limit_block_minutes: Option<i64>,
+    pub instance_id: Option<String>,
+    // thresholds in ℃, m/s and 0..1 probabilities, whatever the units of the users
+    pub advice_umbrella_probability: Option<f64>,
+    pub advice_sunscreen_uv: Option<f64>,
+    pub advice_ice_temperature: Option<f64>,
+    pub advice_layers_spread: Option<f64>,
+    pub compare_temperature: Option<f64>,
+    pub compare_wind: Option<f64>,
+    pub compare_precip_probability: Option<f64>,
 }
 
 impl Config {
@@ -34,17 +82,146 @@ impl Config {
     fn read_from_env() -> Config {
         Config {
             admin_id: std::env::var("ADMIN_ID").ok(),
+            admin_email: std::env::var("ADMIN_EMAIL").ok(),
             viber_api_key: std::env::var("VIBER_API_KEY").ok(),
-            dark_sky_api_key: std::env::var("DARK_SKY_API_KEY").ok(),
+            weather_provider: std::env::var("WEATHER_PROVIDER").ok(),
+            open_weather_map_api_key: std::env::var("OPEN_WEATHER_MAP_API_KEY").ok(),
+            fixtures_dir: std::env::var("FIXTURES_DIR").ok(),
+            fixed_time: std::env::var("FIXED_TIME").ok(),
+            forecast_cache_ttl: std::env::var("FORECAST_CACHE_TTL")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            forecast_cache_db: std::env::var("FORECAST_CACHE_DB")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            api_budgets: std::env::var("API_BUDGETS")
+                .ok()
+                .map(|v| Config::parse_budgets(&v)),
             domain_root_url: std::env::var("DOMAIN_ROOT_URL").ok(),
+            default_latitude: std::env::var("DEFAULT_LATITUDE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            default_longitude: std::env::var("DEFAULT_LONGITUDE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            default_city: std::env::var("DEFAULT_CITY").ok(),
+            brand_name: std::env::var("BRAND_NAME").ok(),
             hosting_root_url: std::env::var("HOSTING_ROOT_URL").ok(),
+            forecast_pictures: std::env::var("FORECAST_PICTURES")
+                .ok()
+                .and_then(|v| v.parse().ok()),
             database_url: std::env::var("DATABASE_URL").ok(),
             google_client_id: std::env::var("GOOGLE_CLIENT_ID").ok(),
             google_client_secret: std::env::var("GOOGLE_CLIENT_SECRET").ok(),
             google_maps_api_key: std::env::var("GOOGLE_MAPS_API_KEY").ok(),
+            rain_alert_threshold: std::env::var("RAIN_ALERT_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            rain_alert_cooldown: std::env::var("RAIN_ALERT_COOLDOWN")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            rain_check_interval: std::env::var("RAIN_CHECK_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            alerts_check_interval: std::env::var("ALERTS_CHECK_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            scheduler_timezone: std::env::var("SCHEDULER_TIMEZONE").ok(),
+            job_schedules: std::env::var("JOB_SCHEDULES")
+                .ok()
+                .map(|v| Config::parse_named(&v)),
+            job_misfires: std::env::var("JOB_MISFIRES")
+                .ok()
+                .map(|v| Config::parse_named(&v)),
+            quota_limits: std::env::var("QUOTA_LIMITS")
+                .ok()
+                .map(|v| Config::parse_named(&v)),
+            quota_exempt: std::env::var("QUOTA_EXEMPT")
+                .ok()
+                .map(|v| v.split(',').map(|id| id.trim().to_owned()).collect()),
+            rate_limit_burst: std::env::var("RATE_LIMIT_BURST")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            rate_limit_per_minute: std::env::var("RATE_LIMIT_PER_MINUTE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            rate_limit_strikes: std::env::var("RATE_LIMIT_STRIKES")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            rate_limit_block_minutes: std::env::var("RATE_LIMIT_BLOCK_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            instance_id: std::env::var("INSTANCE_ID").ok(),
+            advice_umbrella_probability: std::env::var("ADVICE_UMBRELLA_PROBABILITY")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            advice_sunscreen_uv: std::env::var("ADVICE_SUNSCREEN_UV")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            advice_ice_temperature: std::env::var("ADVICE_ICE_TEMPERATURE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            advice_layers_spread: std::env::var("ADVICE_LAYERS_SPREAD")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            compare_temperature: std::env::var("COMPARE_TEMPERATURE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            compare_wind: std::env::var("COMPARE_WIND")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            compare_precip_probability: std::env::var("COMPARE_PRECIP_PROBABILITY")
+                .ok()
+                .and_then(|v| v.parse().ok()),
         }
     }
 
+    pub fn default_location(&self) -> (f64, f64) {
+        (
+            self.default_latitude.unwrap_or(DEFAULT_LATITUDE),
+            self.default_longitude.unwrap_or(DEFAULT_LONGITUDE),
+        )
+    }
+
+    pub fn default_city(&self) -> &str {
+        self.default_city
+            .as_ref()
+            .map(|c| c.as_str())
+            .unwrap_or(DEFAULT_CITY)
+    }
+
+    pub fn brand_name(&self) -> &str {
+        self.brand_name
+            .as_ref()
+            .map(|b| b.as_str())
+            .unwrap_or(BRAND_NAME)
+    }
+
+    fn parse_budgets(value: &str) -> HashMap<String, i32> {
+        value
+            .split(',')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                let provider = parts.next()?.trim();
+                let budget = parts.next()?.trim().parse().ok()?;
+                Some((provider.to_owned(), budget))
+            })
+            .collect()
+    }
+
+    // cron expressions and quota limits have commas of their own
+    fn parse_named(value: &str) -> HashMap<String, String> {
+        value
+            .split(';')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                let job = parts.next()?.trim();
+                let setting = parts.next()?.trim();
+                Some((job.to_owned(), setting.to_owned()))
+            })
+            .collect()
+    }
+
     #[allow(dead_code)]
     fn read_from_toml(app_name: &str) -> Config {
         info!("Reading config");
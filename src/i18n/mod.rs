@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lang {
+    Uk,
+    En,
+    Ru,
+}
+
+impl Default for Lang {
+    fn default() -> Self {
+        Lang::Uk
+    }
+}
+
+impl Lang {
+    pub fn all() -> &'static [Lang] {
+        &[Lang::Uk, Lang::En, Lang::Ru]
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lang::Uk => "uk",
+            Lang::En => "en",
+            Lang::Ru => "ru",
+        }
+    }
+
+    // also locales reported by viber clients, e.g. "en-US"
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let code = code.trim().to_lowercase();
+        let primary = code.split(|c| c == '-' || c == '_').next().unwrap_or("");
+        match primary {
+            "uk" | "ua" => Some(Lang::Uk),
+            "en" => Some(Lang::En),
+            "ru" => Some(Lang::Ru),
+            _ => None,
+        }
+    }
+}
+
+pub struct Catalog {
+    messages: HashMap<Lang, HashMap<String, String>>,
+}
+
+impl Catalog {
+    pub fn load(dir: &str) -> Result<Catalog, failure::Error> {
+        let mut messages = HashMap::new();
+        for lang in Lang::all() {
+            let path = Path::new(dir).join(format!("{}.toml", lang.code()));
+            let text = std::fs::read_to_string(&path)?;
+            let entries: HashMap<String, String> = toml::from_str(text.as_str())?;
+            messages.insert(*lang, entries);
+        }
+        Ok(Catalog { messages })
+    }
+
+    pub fn get(&self, lang: Lang, key: &str) -> String {
+        self.messages
+            .get(&lang)
+            .and_then(|m| m.get(key))
+            .or_else(|| self.messages.get(&Lang::default()).and_then(|m| m.get(key)))
+            .cloned()
+            .unwrap_or_else(|| {
+                warn!("Missing translation for {} ({})", key, lang.code());
+                key.to_owned()
+            })
+    }
+
+    pub fn format(&self, lang: Lang, key: &str, args: &[(&str, &str)]) -> String {
+        let mut text = self.get(lang, key);
+        for &(name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Template error: {}", msg)]
+pub struct TemplateError {
+    pub msg: String,
+}
+
+// one `<name>.<code>.txt` per message and language, see `templates/messages/README.md`
+pub struct Templates {
+    tera: tera::Tera,
+}
+
+impl Templates {
+    pub fn load(dir: &str) -> Result<Templates, failure::Error> {
+        let tera = tera::Tera::new(&format!("{}/*.txt", dir))
+            .map_err(|e| TemplateError { msg: e.to_string() })?;
+        Ok(Templates { tera })
+    }
+
+    pub fn render(
+        &self,
+        lang: Lang,
+        name: &str,
+        ctx: &tera::Context,
+    ) -> Result<String, failure::Error> {
+        let localized = format!("{}.{}.txt", name, lang.code());
+        let template = if self.tera.templates.contains_key(&localized) {
+            localized
+        } else {
+            format!("{}.{}.txt", name, Lang::default().code())
+        };
+        let text = self
+            .tera
+            .render(&template, ctx)
+            .map_err(|e| TemplateError {
+                msg: format!("{}: {}", template, e),
+            })?;
+        Ok(text.trim().to_owned())
+    }
+}
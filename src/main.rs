@@ -17,9 +17,9 @@ extern crate victoria_dom;
 extern crate log;
 extern crate actix;
 extern crate chrono;
+extern crate chrono_tz;
 extern crate dirs;
 extern crate env_logger;
-extern crate forecast;
 extern crate oauth2;
 #[macro_use]
 extern crate failure;
@@ -27,6 +27,7 @@ extern crate reqwest;
 extern crate tera;
 extern crate tokio_openssl;
 extern crate url;
+extern crate uuid;
 
 use actix_web::middleware::identity::{CookieIdentityPolicy, IdentityService};
 use actix_web::{http, middleware, App};
@@ -45,20 +46,26 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 use oauth2::basic::BasicClient;
 use std::cell::Cell;
-use std::collections::HashMap;
 use std::env;
 use std::sync::Mutex;
 use std::sync::RwLock;
-use viber::messages;
 
 pub mod api;
 pub mod bitcoin;
+pub mod clock;
 pub mod common;
 pub mod config;
+pub mod i18n;
 pub mod models;
+pub mod ratelimit;
 pub mod scheduler;
 pub mod schema;
+#[cfg(test)]
+mod testing;
+pub mod units;
+pub mod usage;
 pub mod viber;
+pub mod weather;
 pub mod workers;
 
 // Interval between the task executions where all the notification/alert logic happens.
@@ -71,34 +78,12 @@ static APP_NAME: &str = "viber_alerts";
 pub type AppStateType = Arc<AppState>;
 type PgPool = Pool<ConnectionManager<PgConnection>>;
 
-#[derive(Clone)]
-pub struct ServiceQuota {
-    pub weather_count: u16,
-    pub btc_count: u16,
-}
-
-impl Default for ServiceQuota {
-    fn default() -> Self {
-        ServiceQuota {
-            weather_count: 22,
-            btc_count: 12,
-        }
-    }
-}
-
 impl Actor for WebWorker {
     type Context = Context<Self>;
     fn started(&mut self, ctx: &mut Self::Context) {
-        {
-            let state = &self.app_state;
-            if self
-                .viber
-                .update_subscribers(&mut state.subscribers.write().unwrap())
-                .is_err()
-            {
-                error!("Failed to read subscribers.");
-            };
-        }
+        if self.refresh_subscribers().is_err() {
+            error!("Failed to read subscribers.");
+        };
 
         ctx.run_interval(
             std::time::Duration::new(QUERY_INTERVAL, 0),
@@ -118,29 +103,37 @@ impl Handler<WorkerUnit> for WebWorker {
 
     fn handle(&mut self, msg: WorkerUnit, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
-            WorkerUnit::BTCPrice { user_id } => {
-                let mut quota = self.get_user_quota(&user_id);
-                if quota.btc_count > 0 {
-                    self.send_btc_price(&user_id);
-                    quota.btc_count -= 1;
-                    self.set_user_quota(&user_id, quota);
-                } else {
-                    self.viber
-                        .send_text_to(
-                            "Max request count exceeded.",
-                            &user_id,
-                            Some(common::get_default_keyboard()),
-                        )
-                        .unwrap_or_default();
+            WorkerUnit::BTCPrice { user_id } => match self.take_quota(&user_id, "bitcoin") {
+                Ok(true) => {
+                    if let Err(e) = self.send_btc_price(&user_id) {
+                        error!("Could not send bitcoin price to {}. {:?}", &user_id, e);
+                    }
                 }
-            }
+                Ok(false) => {
+                    let lang = self.user_lang(&user_id);
+                    self.reply(
+                        &user_id,
+                        lang,
+                        &self.app_state.i18n.get(lang, "quota_exceeded"),
+                    )
+                    .unwrap_or_default();
+                }
+                Err(e) => error!("Can't take the quota of {}. {:?}", &user_id, e),
+            },
             WorkerUnit::TomorrowForecast { user_id } => {
-                self.send_forecast_for_tomorrow(&self.last_response, &user_id, "")
+                self.default_forecast_for_tomorrow(&user_id)
                     .map_err(|_| {
                         error!("Can't send forecast for tomorrow to {}", &user_id);
                     })
                     .unwrap_or_default();
             }
+            WorkerUnit::Forecast { user_id, kind } => {
+                self.default_forecast(&user_id, kind)
+                    .map_err(|e| {
+                        error!("Can't send {:?} forecast to {}. {:?}", kind, &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
             WorkerUnit::ImmediateTomorrowForecast { user_id, lat, lon } => {
                 self.immediate_forecast_for_tomorrow(&user_id, lat, lon)
                     .map_err(|_| {
@@ -148,11 +141,105 @@ impl Handler<WorkerUnit> for WebWorker {
                     })
                     .unwrap_or_default();
             }
+            WorkerUnit::SetNotifyTime { user_id, minutes } => {
+                self.set_notify_time(&user_id, minutes)
+                    .map_err(|e| {
+                        error!("Can't set notification time for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::SetTimezone { user_id, timezone } => {
+                self.set_timezone(&user_id, &timezone)
+                    .map_err(|e| {
+                        error!("Can't set timezone for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::SetLanguage { user_id, language } => {
+                self.set_language(&user_id, &language)
+                    .map_err(|e| {
+                        error!("Can't set language for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::SetUnits { user_id, units } => {
+                self.set_units(&user_id, &units)
+                    .map_err(|e| {
+                        error!("Can't set units for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::SetRainAlerts { user_id, enabled } => {
+                self.set_rain_alerts(&user_id, enabled)
+                    .map_err(|e| {
+                        error!("Can't set rain alerts for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::SetForecastFields { user_id, fields } => {
+                self.set_forecast_fields(&user_id, &fields)
+                    .map_err(|e| {
+                        error!("Can't set forecast fields for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::AddRule { user_id, rule } => {
+                self.add_rule(&user_id, &rule)
+                    .map_err(|e| {
+                        error!("Can't add a rule for {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::ListRules { user_id } => {
+                self.list_rules(&user_id)
+                    .map_err(|e| {
+                        error!("Can't list rules of {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::DeleteRule { user_id, id } => {
+                self.delete_rule(&user_id, id)
+                    .map_err(|e| {
+                        error!("Can't delete rule {} of {}. {:?}", id, &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::RegisterSender { user_id, language } => {
+                self.register_sender(&user_id, language.as_ref().map(|l| l.as_str()))
+                    .map_err(|e| {
+                        error!("Can't register user {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::LinkAccount { user_id, code } => {
+                self.link_account(&user_id, &code)
+                    .map_err(|e| {
+                        error!("Can't link the account of {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::ShowQuota { user_id } => {
+                self.show_quota(&user_id)
+                    .map_err(|e| {
+                        error!("Can't show the quota of {}. {:?}", &user_id, e);
+                    })
+                    .unwrap_or_default();
+            }
+            WorkerUnit::Unblock { user_id, blocked } => {
+                self.unblock(&user_id, &blocked)
+                    .map_err(|e| {
+                        error!("Can't unblock {}. {:?}", &blocked, e);
+                    })
+                    .unwrap_or_default();
+            }
             WorkerUnit::UnknownCommand { user_id } => {
-                self.viber.send_text_to("Невідома команда. Відправте місцезнаходження, щоб дізнатися прогноз на завтра.",
-                                        &user_id,
-                                        Some(common::get_default_keyboard())
-                ).unwrap_or_default();
+                let lang = self.user_lang(&user_id);
+                self.reply(
+                    &user_id,
+                    lang,
+                    &self.app_state.i18n.get(lang, "unknown_command"),
+                )
+                .unwrap_or_default();
             }
         };
         ()
@@ -162,32 +249,54 @@ impl Handler<WorkerUnit> for WebWorker {
 pub struct AppState {
     pub addr: Mutex<Cell<Option<Addr<WebWorker>>>>,
     pub config: config::Config,
-    pub subscribers: RwLock<Vec<messages::Member>>,
-    pub last_text_broadcast: RwLock<scheduler::TryTillSuccess>,
-    pub last_btc_update: RwLock<scheduler::TryTillSuccess>,
+    pub jobs: RwLock<scheduler::Registry>,
     pub pool: PgPool,
-    pub quota: RwLock<HashMap<String, ServiceQuota>>,
     pub auth_client: Mutex<Cell<Option<BasicClient>>>,
+    pub i18n: i18n::Catalog,
+    pub messages: i18n::Templates,
+    pub weather: Box<weather::WeatherProvider>,
+    pub clock: Arc<clock::Clock>,
+    pub usage: Arc<usage::UsageTracker>,
+    pub inbound: ratelimit::RateLimiter,
     template: tera::Tera, // <- store tera template in application state
 }
 
 impl AppState {
-    pub fn new(config: &config::Config, pool: PgPool) -> AppState {
+    pub fn new(config: &config::Config, pool: PgPool) -> Result<AppState, failure::Error> {
         let viber_api_key = config.viber_api_key.clone();
         let admin_id = config.admin_id.clone();
 
-        let tera = tera::Tera::new("templates/**/*").expect("Failed to load templates");
-        AppState {
+        let tera = tera::Tera::new("templates/*.html").expect("Failed to load templates");
+        let catalog = i18n::Catalog::load("locales").expect("Failed to load translations");
+        let messages =
+            i18n::Templates::load("templates/messages").expect("Failed to load message templates");
+        let clock = clock::from_config(config);
+        let usage = Arc::new(usage::UsageTracker::new(
+            pool.clone(),
+            config.api_budgets.clone().unwrap_or_default(),
+        ));
+        let weather = weather::cached_from_config(config, &pool, usage.clone(), clock.clone())?;
+        // spam is measured in real time, also when the forecasts are replayed at a fixed time
+        let inbound =
+            ratelimit::RateLimiter::new(config, pool.clone(), Arc::new(clock::SystemClock));
+        Ok(AppState {
             config: (*config).clone(),
-            subscribers: RwLock::new(Vec::new()),
-            last_text_broadcast: RwLock::new(scheduler::TryTillSuccess::new()),
-            last_btc_update: RwLock::new(scheduler::TryTillSuccess::new()),
+            jobs: RwLock::new(workers::jobs::registry(
+                config,
+                &pool,
+                clock.now().timestamp(),
+            )),
             template: tera,
-            quota: RwLock::new(HashMap::new()),
+            i18n: catalog,
+            messages,
+            weather,
+            clock,
+            usage,
+            inbound,
             pool,
             auth_client: Mutex::new(Cell::new(None)),
             addr: Mutex::new(Cell::new(None)),
-        }
+        })
     }
 }
 
@@ -214,7 +323,13 @@ fn main() {
     let pool = r2d2::Pool::builder()
         .build(manager)
         .expect("Failed to create pool.");
-    let state = Arc::new(AppState::new(&config, pool));
+    let state = match AppState::new(&config, pool) {
+        Ok(state) => Arc::new(state),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
     let oauth_client = build_google_auth_client(&config);
     state.auth_client.lock().unwrap().set(Some(oauth_client));
     let _state = state.clone();
@@ -242,6 +357,19 @@ fn main() {
             .resource("/google6e03bff5229f1e21.html", |r| {
                 r.f(|_| "google-site-verification: google6e03bff5229f1e21.html")
             })
+            .resource("/api/settings/", |r| {
+                r.method(http::Method::POST).with(api::settings)
+            })
+            .resource("/api/rules/", |r| {
+                r.method(http::Method::POST).with(api::add_rule)
+            })
+            .resource("/api/rules/delete/", |r| {
+                r.method(http::Method::POST).with(api::delete_rule)
+            })
+            .resource("/api/jobs/", |r| r.f(api::jobs))
+            .resource("/api/jobs/action/", |r| {
+                r.method(http::Method::POST).with(api::job_action)
+            })
             .resource("/list", |r| r.method(http::Method::GET).with(api::list))
             .resource("/api/viber/webhook/", |r| r.f(api::viber_webhook))
     })
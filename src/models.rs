@@ -1,5 +1,22 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz;
+use chrono_tz::Tz;
 use diesel::*;
+use i18n::Lang;
+use schema::alert_rules;
+use schema::api_calls;
+use schema::api_usage;
+use schema::forecast_cache;
+use schema::job_leases;
+use schema::job_runs;
+use schema::quota_usage;
+use schema::scheduled_jobs;
+use schema::sent_alerts;
+use schema::spam_strikes;
+use schema::subscribers;
 use schema::users;
+use units::UnitSystem;
+use workers::rules;
 
 #[derive(Insertable)]
 #[table_name = "users"]
@@ -15,6 +32,21 @@ pub struct User {
     pub email: Option<String>,
     pub viber_id: Option<String>,
     pub broadcast: bool,
+    // minutes after the local midnight
+    pub notify_time: Option<i16>,
+    pub timezone: String,
+    pub last_notified: Option<NaiveDate>,
+    pub language: Option<String>,
+    pub units: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub rain_alerts: bool,
+    pub last_rain_alert: Option<NaiveDateTime>,
+    // comma separated, the default ones when missing
+    pub forecast_fields: Option<String>,
+    // code the web user sends to the bot to link their viber account
+    pub link_code: Option<String>,
+    pub link_code_expires: Option<NaiveDateTime>,
 }
 
 impl User {
@@ -35,4 +67,736 @@ impl User {
             .unwrap();
         results.pop()
     }
+
+    pub fn by_viber_id(id: &str, conn: &PgConnection) -> Option<User> {
+        let mut results: Vec<User> = users::dsl::users
+            .filter(users::dsl::viber_id.eq(id))
+            .load(conn)
+            .unwrap();
+        results.pop()
+    }
+
+    pub fn register_viber(id: &str, conn: &PgConnection) -> QueryResult<User> {
+        match User::by_viber_id(id, conn) {
+            Some(user) => Ok(user),
+            None => User::insert(
+                NewUser {
+                    email: None,
+                    viber_id: Some(id),
+                    broadcast: false,
+                },
+                conn,
+            ),
+        }
+    }
+
+    pub fn register_viber_with_language(
+        id: &str,
+        language: Option<&str>,
+        conn: &PgConnection,
+    ) -> QueryResult<User> {
+        let user = User::register_viber(id, conn)?;
+        match (user.language.as_ref(), language.and_then(Lang::from_code)) {
+            (None, Some(lang)) => {
+                User::set_language(user.id, lang, conn)?;
+                User::register_viber(id, conn)
+            }
+            _ => Ok(user),
+        }
+    }
+
+    pub fn lang(&self) -> Lang {
+        self.language
+            .as_ref()
+            .and_then(|l| Lang::from_code(l))
+            .unwrap_or_default()
+    }
+
+    pub fn set_language(user_id: i32, lang: Lang, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::language.eq(lang.code()))
+            .execute(conn)
+    }
+
+    pub fn units(&self) -> UnitSystem {
+        self.units
+            .as_ref()
+            .and_then(|u| UnitSystem::from_code(u))
+            .unwrap_or_default()
+    }
+
+    pub fn set_units(user_id: i32, units: UnitSystem, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::units.eq(units.code()))
+            .execute(conn)
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::Europe::Kiev)
+    }
+
+    pub fn location(&self) -> Option<(f64, f64)> {
+        match (self.latitude, self.longitude) {
+            (Some(lat), Some(lon)) => Some((lat, lon)),
+            _ => None,
+        }
+    }
+
+    pub fn set_location(
+        user_id: i32,
+        lat: f64,
+        lon: f64,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set((users::dsl::latitude.eq(lat), users::dsl::longitude.eq(lon)))
+            .execute(conn)
+    }
+
+    pub fn set_forecast_fields(
+        user_id: i32,
+        fields: Option<&str>,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::forecast_fields.eq(fields))
+            .execute(conn)
+    }
+
+    pub fn set_rain_alerts(user_id: i32, enabled: bool, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::rain_alerts.eq(enabled))
+            .execute(conn)
+    }
+
+    pub fn set_last_rain_alert(
+        user_id: i32,
+        time: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::last_rain_alert.eq(time))
+            .execute(conn)
+    }
+
+    pub fn with_rain_alerts(conn: &PgConnection) -> QueryResult<Vec<User>> {
+        users::dsl::users
+            .filter(users::dsl::rain_alerts.eq(true))
+            .filter(users::dsl::latitude.is_not_null())
+            .filter(users::dsl::longitude.is_not_null())
+            .filter(users::dsl::viber_id.is_not_null())
+            .load::<User>(conn)
+    }
+
+    // users with a home location or a daily forecast for the default city
+    pub fn monitored(conn: &PgConnection) -> QueryResult<Vec<User>> {
+        users::dsl::users
+            .filter(users::dsl::viber_id.is_not_null())
+            .filter(
+                users::dsl::latitude
+                    .is_not_null()
+                    .or(users::dsl::notify_time.is_not_null()),
+            )
+            .load::<User>(conn)
+    }
+
+    pub fn by_ids(ids: &[i32], conn: &PgConnection) -> QueryResult<Vec<User>> {
+        users::dsl::users
+            .filter(users::dsl::id.eq_any(ids))
+            .load::<User>(conn)
+    }
+
+    pub fn with_notify_time(conn: &PgConnection) -> QueryResult<Vec<User>> {
+        users::dsl::users
+            .filter(users::dsl::notify_time.is_not_null())
+            .filter(users::dsl::viber_id.is_not_null())
+            .load::<User>(conn)
+    }
+
+    pub fn set_notify_time(
+        user_id: i32,
+        time: Option<i16>,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::notify_time.eq(time))
+            .execute(conn)
+    }
+
+    pub fn set_timezone(user_id: i32, tz: &str, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::timezone.eq(tz))
+            .execute(conn)
+    }
+
+    pub fn set_link_code(
+        user_id: i32,
+        code: &str,
+        expires: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set((
+                users::dsl::link_code.eq(code),
+                users::dsl::link_code_expires.eq(expires),
+            ))
+            .execute(conn)
+    }
+
+    // the email and the rules of the web account move to the viber user
+    pub fn link(
+        code: &str,
+        viber_id: &str,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<Option<User>> {
+        conn.transaction(|| {
+            let web = match users::dsl::users
+                .filter(users::dsl::link_code.eq(code))
+                .filter(users::dsl::link_code_expires.gt(now))
+                .first::<User>(conn)
+                .optional()?
+            {
+                Some(web) => web,
+                None => return Ok(None),
+            };
+            let viber = User::register_viber(viber_id, conn)?;
+            if web.id != viber.id {
+                if web.viber_id.is_some() {
+                    // the email moves on to another viber account
+                    diesel::update(users::dsl::users.find(web.id))
+                        .set(users::dsl::email.eq(None::<String>))
+                        .execute(conn)?;
+                } else {
+                    diesel::update(
+                        alert_rules::dsl::alert_rules.filter(alert_rules::dsl::user_id.eq(web.id)),
+                    )
+                    .set(alert_rules::dsl::user_id.eq(viber.id))
+                    .execute(conn)?;
+                    diesel::delete(users::dsl::users.find(web.id)).execute(conn)?;
+                }
+            }
+            diesel::update(users::dsl::users.find(viber.id))
+                .set((
+                    users::dsl::email.eq(web.email),
+                    users::dsl::link_code.eq(None::<String>),
+                    users::dsl::link_code_expires.eq(None::<NaiveDateTime>),
+                ))
+                .get_result(conn)
+                .map(Some)
+        })
+    }
+
+    pub fn set_last_notified(
+        user_id: i32,
+        date: NaiveDate,
+        conn: &PgConnection,
+    ) -> QueryResult<usize> {
+        diesel::update(users::dsl::users.find(user_id))
+            .set(users::dsl::last_notified.eq(date))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "sent_alerts"]
+pub struct NewSentAlert<'a> {
+    pub viber_id: &'a str,
+    pub alert_id: &'a str,
+    pub expires: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct SentAlert {
+    pub id: i32,
+    pub viber_id: String,
+    pub alert_id: String,
+    pub expires: NaiveDateTime,
+    pub sent_at: NaiveDateTime,
+}
+
+impl SentAlert {
+    pub fn insert(alert: NewSentAlert, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(sent_alerts::table)
+            .values(&alert)
+            .on_conflict_do_nothing()
+            .execute(conn)
+    }
+
+    pub fn exists(
+        viber_id: &str,
+        alert_id: &str,
+        expires: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<bool> {
+        sent_alerts::dsl::sent_alerts
+            .filter(sent_alerts::dsl::viber_id.eq(viber_id))
+            .filter(sent_alerts::dsl::alert_id.eq(alert_id))
+            .filter(sent_alerts::dsl::expires.eq(expires))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count > 0)
+    }
+
+    pub fn delete_expired(before: NaiveDateTime, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::delete(sent_alerts::dsl::sent_alerts.filter(sent_alerts::dsl::expires.lt(before)))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "alert_rules"]
+pub struct NewAlertRule<'a> {
+    pub user_id: i32,
+    pub field: &'a str,
+    pub op: &'a str,
+    pub value: f64,
+    pub units: &'a str,
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct AlertRule {
+    pub id: i32,
+    pub user_id: i32,
+    pub field: String,
+    pub op: String,
+    pub value: f64,
+    pub last_triggered: Option<NaiveDate>,
+    // the units of the value, as the user typed it
+    pub units: String,
+}
+
+impl AlertRule {
+    pub fn insert(rule: NewAlertRule, conn: &PgConnection) -> QueryResult<AlertRule> {
+        diesel::insert_into(alert_rules::table)
+            .values(&rule)
+            .get_result(conn)
+    }
+
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<AlertRule>> {
+        alert_rules::dsl::alert_rules
+            .order(alert_rules::id.asc())
+            .load::<AlertRule>(conn)
+    }
+
+    pub fn of_user(user_id: i32, conn: &PgConnection) -> QueryResult<Vec<AlertRule>> {
+        alert_rules::dsl::alert_rules
+            .filter(alert_rules::dsl::user_id.eq(user_id))
+            .order(alert_rules::id.asc())
+            .load::<AlertRule>(conn)
+    }
+
+    pub fn delete(id: i32, user_id: i32, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::delete(
+            alert_rules::dsl::alert_rules
+                .filter(alert_rules::dsl::id.eq(id))
+                .filter(alert_rules::dsl::user_id.eq(user_id)),
+        )
+        .execute(conn)
+    }
+
+    pub fn set_last_triggered(id: i32, date: NaiveDate, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(alert_rules::dsl::alert_rules.find(id))
+            .set(alert_rules::dsl::last_triggered.eq(date))
+            .execute(conn)
+    }
+
+    pub fn units(&self) -> UnitSystem {
+        UnitSystem::from_code(&self.units).unwrap_or_default()
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "#{} {} {} {} {}",
+            self.id,
+            self.field,
+            self.op,
+            self.value,
+            rules::unit(&self.field, self.units())
+        )
+        .trim_end()
+        .to_owned()
+    }
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "forecast_cache"]
+pub struct CachedForecast {
+    pub lat_key: i32,
+    pub lon_key: i32,
+    pub lang: String,
+    pub data: String,
+    pub fetched_at: NaiveDateTime,
+}
+
+impl CachedForecast {
+    pub fn find(
+        lat_key: i32,
+        lon_key: i32,
+        lang: &str,
+        conn: &PgConnection,
+    ) -> QueryResult<Option<CachedForecast>> {
+        forecast_cache::dsl::forecast_cache
+            .find((lat_key, lon_key, lang))
+            .first(conn)
+            .optional()
+    }
+
+    pub fn upsert(&self, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(forecast_cache::table)
+            .values(self)
+            .on_conflict((
+                forecast_cache::dsl::lat_key,
+                forecast_cache::dsl::lon_key,
+                forecast_cache::dsl::lang,
+            ))
+            .do_update()
+            .set((
+                forecast_cache::dsl::data.eq(&self.data),
+                forecast_cache::dsl::fetched_at.eq(self.fetched_at),
+            ))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "api_calls"]
+pub struct NewApiCall<'a> {
+    pub provider: &'a str,
+    pub endpoint: &'a str,
+    pub latency_ms: i32,
+    pub success: bool,
+}
+
+impl<'a> NewApiCall<'a> {
+    pub fn insert(&self, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(api_calls::table)
+            .values(self)
+            .execute(conn)
+    }
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct ApiUsage {
+    pub provider: String,
+    pub day: NaiveDate,
+    pub calls: i32,
+    pub warned: bool,
+}
+
+impl ApiUsage {
+    pub fn find(
+        provider: &str,
+        day: NaiveDate,
+        conn: &PgConnection,
+    ) -> QueryResult<Option<ApiUsage>> {
+        api_usage::dsl::api_usage
+            .find((provider, day))
+            .first(conn)
+            .optional()
+    }
+
+    pub fn of_day(day: NaiveDate, conn: &PgConnection) -> QueryResult<Vec<ApiUsage>> {
+        api_usage::dsl::api_usage
+            .filter(api_usage::dsl::day.eq(day))
+            .order(api_usage::dsl::provider.asc())
+            .load(conn)
+    }
+
+    pub fn increment(provider: &str, day: NaiveDate, conn: &PgConnection) -> QueryResult<ApiUsage> {
+        diesel::insert_into(api_usage::table)
+            .values((
+                api_usage::dsl::provider.eq(provider),
+                api_usage::dsl::day.eq(day),
+                api_usage::dsl::calls.eq(1),
+            ))
+            .on_conflict((api_usage::dsl::provider, api_usage::dsl::day))
+            .do_update()
+            .set(api_usage::dsl::calls.eq(api_usage::dsl::calls + 1))
+            .get_result(conn)
+    }
+
+    pub fn set_warned(provider: &str, day: NaiveDate, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(api_usage::dsl::api_usage.find((provider, day)))
+            .set(api_usage::dsl::warned.eq(true))
+            .execute(conn)
+    }
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize)]
+#[table_name = "scheduled_jobs"]
+pub struct ScheduledJob {
+    pub name: String,
+    pub last_run: Option<NaiveDateTime>,
+    pub last_success: Option<NaiveDateTime>,
+    pub next_due: Option<NaiveDateTime>,
+    pub failures: i32,
+    pub paused: bool,
+}
+
+impl ScheduledJob {
+    pub fn find(name: &str, conn: &PgConnection) -> QueryResult<Option<ScheduledJob>> {
+        scheduled_jobs::dsl::scheduled_jobs
+            .find(name)
+            .first(conn)
+            .optional()
+    }
+
+    pub fn upsert(&self, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(scheduled_jobs::table)
+            .values(self)
+            .on_conflict(scheduled_jobs::dsl::name)
+            .do_update()
+            .set((
+                scheduled_jobs::dsl::last_run.eq(self.last_run),
+                scheduled_jobs::dsl::last_success.eq(self.last_success),
+                scheduled_jobs::dsl::next_due.eq(self.next_due),
+                scheduled_jobs::dsl::failures.eq(self.failures),
+                scheduled_jobs::dsl::paused.eq(self.paused),
+            ))
+            .execute(conn)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "job_runs"]
+pub struct NewJobRun<'a> {
+    pub job: &'a str,
+    pub started: NaiveDateTime,
+    pub finished: NaiveDateTime,
+    pub success: bool,
+    pub error: Option<String>,
+    pub messages_sent: i32,
+}
+
+impl<'a> NewJobRun<'a> {
+    pub fn insert(&self, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(job_runs::table)
+            .values(self)
+            .execute(conn)
+    }
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct JobRun {
+    pub id: i32,
+    pub job: String,
+    pub started: NaiveDateTime,
+    pub finished: NaiveDateTime,
+    pub success: bool,
+    pub error: Option<String>,
+    pub messages_sent: i32,
+}
+
+impl JobRun {
+    pub fn recent(job: &str, limit: i64, conn: &PgConnection) -> QueryResult<Vec<JobRun>> {
+        job_runs::dsl::job_runs
+            .filter(job_runs::dsl::job.eq(job))
+            .order(job_runs::dsl::started.desc())
+            .limit(limit)
+            .load(conn)
+    }
+
+    pub fn delete_older(before: NaiveDateTime, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::delete(job_runs::dsl::job_runs.filter(job_runs::dsl::started.lt(before)))
+            .execute(conn)
+    }
+}
+
+// a job is leased to one instance, so that it runs once when several share the database
+pub struct JobLease;
+
+impl JobLease {
+    // returns whether the lease is taken
+    pub fn acquire(
+        name: &str,
+        holder: &str,
+        now: NaiveDateTime,
+        expires: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<bool> {
+        diesel::sql_query(
+            "INSERT INTO job_leases (name, holder, expires) VALUES ($1, $2, $4) \
+             ON CONFLICT (name) DO UPDATE SET holder = $2, expires = $4 \
+             WHERE job_leases.holder = $2 OR job_leases.expires < $3",
+        )
+        .bind::<sql_types::Varchar, _>(name)
+        .bind::<sql_types::Varchar, _>(holder)
+        .bind::<sql_types::Timestamp, _>(now)
+        .bind::<sql_types::Timestamp, _>(expires)
+        .execute(conn)
+        .map(|rows| rows == 1)
+    }
+
+    pub fn release(name: &str, holder: &str, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::delete(
+            job_leases::dsl::job_leases
+                .filter(job_leases::dsl::name.eq(name))
+                .filter(job_leases::dsl::holder.eq(holder)),
+        )
+        .execute(conn)
+    }
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize)]
+#[table_name = "subscribers"]
+pub struct Subscriber {
+    pub viber_id: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub role: String,
+}
+
+impl Subscriber {
+    pub fn all(conn: &PgConnection) -> QueryResult<Vec<Subscriber>> {
+        subscribers::dsl::subscribers
+            .order(subscribers::dsl::name.asc())
+            .load(conn)
+    }
+
+    pub fn exists(viber_id: &str, conn: &PgConnection) -> QueryResult<bool> {
+        subscribers::dsl::subscribers
+            .find(viber_id)
+            .count()
+            .get_result::<i64>(conn)
+            .map(|count| count > 0)
+    }
+
+    pub fn replace_all(members: &[Subscriber], conn: &PgConnection) -> QueryResult<()> {
+        conn.transaction(|| {
+            diesel::delete(subscribers::table).execute(conn)?;
+            diesel::insert_into(subscribers::table)
+                .values(members)
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+}
+
+pub struct QuotaUsage;
+
+impl QuotaUsage {
+    // counted under a lock, so that concurrent requests can't exceed the limits
+    pub fn take(
+        viber_id: &str,
+        command: &str,
+        now: NaiveDateTime,
+        limits: &[(i64, NaiveDateTime)],
+        conn: &PgConnection,
+    ) -> QueryResult<bool> {
+        conn.transaction(|| {
+            diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                .bind::<sql_types::Varchar, _>(viber_id)
+                .execute(conn)?;
+            if let Some(oldest) = limits.iter().map(|&(_, since)| since).min() {
+                diesel::delete(
+                    quota_usage::dsl::quota_usage
+                        .filter(quota_usage::dsl::viber_id.eq(viber_id))
+                        .filter(quota_usage::dsl::command.eq(command))
+                        .filter(quota_usage::dsl::used_at.lt(oldest)),
+                )
+                .execute(conn)?;
+            }
+            for &(count, since) in limits {
+                if QuotaUsage::count(viber_id, command, since, conn)? >= count {
+                    return Ok(false);
+                }
+            }
+            diesel::insert_into(quota_usage::table)
+                .values((
+                    quota_usage::dsl::viber_id.eq(viber_id),
+                    quota_usage::dsl::command.eq(command),
+                    quota_usage::dsl::used_at.eq(now),
+                ))
+                .execute(conn)?;
+            Ok(true)
+        })
+    }
+
+    pub fn count(
+        viber_id: &str,
+        command: &str,
+        since: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<i64> {
+        quota_usage::dsl::quota_usage
+            .filter(quota_usage::dsl::viber_id.eq(viber_id))
+            .filter(quota_usage::dsl::command.eq(command))
+            .filter(quota_usage::dsl::used_at.ge(since))
+            .count()
+            .get_result(conn)
+    }
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "spam_strikes"]
+pub struct SpamStrike {
+    pub viber_id: String,
+    // each at most the strike window after the previous one
+    pub strikes: i32,
+    pub last_strike: NaiveDateTime,
+    pub blocked_until: Option<NaiveDateTime>,
+}
+
+impl SpamStrike {
+    // starts the count over if the previous cooldown was before `since`
+    pub fn record(
+        viber_id: &str,
+        now: NaiveDateTime,
+        since: NaiveDateTime,
+        conn: &PgConnection,
+    ) -> QueryResult<i32> {
+        conn.transaction(|| {
+            let strikes = match spam_strikes::dsl::spam_strikes
+                .find(viber_id)
+                .first::<SpamStrike>(conn)
+                .optional()?
+            {
+                Some(ref strike) if strike.last_strike >= since => strike.strikes + 1,
+                _ => 1,
+            };
+            diesel::insert_into(spam_strikes::table)
+                .values((
+                    spam_strikes::dsl::viber_id.eq(viber_id),
+                    spam_strikes::dsl::strikes.eq(strikes),
+                    spam_strikes::dsl::last_strike.eq(now),
+                ))
+                .on_conflict(spam_strikes::dsl::viber_id)
+                .do_update()
+                .set((
+                    spam_strikes::dsl::strikes.eq(strikes),
+                    spam_strikes::dsl::last_strike.eq(now),
+                ))
+                .execute(conn)?;
+            Ok(strikes)
+        })
+    }
+
+    pub fn block(viber_id: &str, until: NaiveDateTime, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::update(spam_strikes::dsl::spam_strikes.find(viber_id))
+            .set((
+                spam_strikes::dsl::strikes.eq(0),
+                spam_strikes::dsl::blocked_until.eq(Some(until)),
+            ))
+            .execute(conn)
+    }
+
+    pub fn blocked_until(
+        viber_id: &str,
+        conn: &PgConnection,
+    ) -> QueryResult<Option<NaiveDateTime>> {
+        spam_strikes::dsl::spam_strikes
+            .find(viber_id)
+            .select(spam_strikes::dsl::blocked_until)
+            .first::<Option<NaiveDateTime>>(conn)
+            .optional()
+            .map(|until| until.and_then(|u| u))
+    }
+
+    // returns whether the user was blocked at `now`
+    pub fn unblock(viber_id: &str, now: NaiveDateTime, conn: &PgConnection) -> QueryResult<bool> {
+        let blocked = SpamStrike::blocked_until(viber_id, conn)?.map_or(false, |until| until > now);
+        diesel::delete(spam_strikes::dsl::spam_strikes.find(viber_id)).execute(conn)?;
+        Ok(blocked)
+    }
 }
@@ -0,0 +1,335 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use clock::Clock;
+use config::Config;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use models::SpamStrike;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+static BURST: f64 = 10.0;
+static PER_MINUTE: f64 = 6.0;
+static STRIKES: i32 = 3;
+static BLOCK_MINUTES: i64 = 60;
+// cooldowns further apart than this don't add up to a block
+static STRIKE_WINDOW_MINUTES: i64 = 60;
+static MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    // answered with a notice to wait this many seconds
+    Cooldown(i64),
+    // answered with a notice of the block for this many minutes
+    Blocked(i64),
+    Drop,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+    // the user has been told to wait since the bucket ran out
+    notified: bool,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: DateTime<Utc>, burst: f64, per_minute: f64) {
+        let elapsed = now.signed_duration_since(self.updated).num_milliseconds();
+        let seconds = (elapsed as f64 / 1000.0).max(0.0);
+        self.tokens = (self.tokens + seconds * per_minute / 60.0).min(burst);
+        self.updated = now;
+    }
+}
+
+// the cooldowns and blocks, shared between the instances
+trait StrikeStore: Send + Sync {
+    fn blocked_until(&self, viber_id: &str) -> Result<Option<NaiveDateTime>, failure::Error>;
+
+    // returns the strikes in a row with this one, the count starts over after a strike before `since`
+    fn record(
+        &self,
+        viber_id: &str,
+        now: NaiveDateTime,
+        since: NaiveDateTime,
+    ) -> Result<i32, failure::Error>;
+
+    fn block(&self, viber_id: &str, until: NaiveDateTime) -> Result<(), failure::Error>;
+
+    // returns whether the user was blocked at `now`
+    fn unblock(&self, viber_id: &str, now: NaiveDateTime) -> Result<bool, failure::Error>;
+}
+
+struct DbStrikes {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl StrikeStore for DbStrikes {
+    fn blocked_until(&self, viber_id: &str) -> Result<Option<NaiveDateTime>, failure::Error> {
+        let conn = self.pool.get()?;
+        Ok(SpamStrike::blocked_until(viber_id, &conn)?)
+    }
+
+    fn record(
+        &self,
+        viber_id: &str,
+        now: NaiveDateTime,
+        since: NaiveDateTime,
+    ) -> Result<i32, failure::Error> {
+        let conn = self.pool.get()?;
+        Ok(SpamStrike::record(viber_id, now, since, &conn)?)
+    }
+
+    fn block(&self, viber_id: &str, until: NaiveDateTime) -> Result<(), failure::Error> {
+        let conn = self.pool.get()?;
+        SpamStrike::block(viber_id, until, &conn)?;
+        Ok(())
+    }
+
+    fn unblock(&self, viber_id: &str, now: NaiveDateTime) -> Result<bool, failure::Error> {
+        let conn = self.pool.get()?;
+        Ok(SpamStrike::unblock(viber_id, now, &conn)?)
+    }
+}
+
+// buckets are per instance, the cooldowns and blocks are shared in the database
+pub struct RateLimiter {
+    store: Box<StrikeStore>,
+    clock: Arc<Clock>,
+    admin_id: Option<String>,
+    burst: f64,
+    per_minute: f64,
+    strikes: i32,
+    block_minutes: i64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        config: &Config,
+        pool: Pool<ConnectionManager<PgConnection>>,
+        clock: Arc<Clock>,
+    ) -> RateLimiter {
+        RateLimiter::with_store(config, Box::new(DbStrikes { pool }), clock)
+    }
+
+    fn with_store(config: &Config, store: Box<StrikeStore>, clock: Arc<Clock>) -> RateLimiter {
+        RateLimiter {
+            store,
+            clock,
+            admin_id: config.admin_id.clone(),
+            burst: config.rate_limit_burst.unwrap_or(BURST).max(1.0),
+            per_minute: config.rate_limit_per_minute.unwrap_or(PER_MINUTE).max(0.1),
+            strikes: config.rate_limit_strikes.unwrap_or(STRIKES),
+            block_minutes: config.rate_limit_block_minutes.unwrap_or(BLOCK_MINUTES),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // a user out of messages is told to wait once, further messages are dropped
+    pub fn check(&self, viber_id: &str) -> Verdict {
+        if self.admin_id.as_ref().map(|a| a.as_str()) == Some(viber_id) {
+            return Verdict::Allow;
+        }
+        let instant = self.clock.now();
+        let now = instant.naive_utc();
+        match self.store.blocked_until(viber_id) {
+            Ok(Some(until)) if until > now => return Verdict::Drop,
+            Ok(_) => {}
+            // let the messages through rather than ignore everybody
+            Err(e) => error!("Can't check whether {} is blocked. {:?}", viber_id, e),
+        }
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            if buckets.len() >= MAX_BUCKETS {
+                let (burst, per_minute) = (self.burst, self.per_minute);
+                buckets.retain(|_, bucket| {
+                    bucket.refill(instant, burst, per_minute);
+                    bucket.tokens < burst
+                });
+            }
+            let bucket = buckets
+                .entry(viber_id.to_owned())
+                .or_insert_with(|| Bucket {
+                    tokens: self.burst,
+                    updated: instant,
+                    notified: false,
+                });
+            bucket.refill(instant, self.burst, self.per_minute);
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                bucket.notified = false;
+                return Verdict::Allow;
+            }
+            if bucket.notified {
+                return Verdict::Drop;
+            }
+            bucket.notified = true;
+            ((1.0 - bucket.tokens) * 60.0 / self.per_minute).ceil() as i64
+        };
+        match self.strike(viber_id, now) {
+            Ok(true) => {
+                info!("Blocking {} for {} min.", viber_id, self.block_minutes);
+                Verdict::Blocked(self.block_minutes)
+            }
+            Ok(false) => Verdict::Cooldown(wait),
+            Err(e) => {
+                error!("Can't record a cooldown of {}. {:?}", viber_id, e);
+                Verdict::Cooldown(wait)
+            }
+        }
+    }
+
+    // returns whether the user was blocked
+    pub fn unblock(&self, viber_id: &str) -> Result<bool, failure::Error> {
+        self.buckets.lock().unwrap().remove(viber_id);
+        self.store.unblock(viber_id, self.clock.now().naive_utc())
+    }
+
+    // returns whether the user is blocked now
+    fn strike(&self, viber_id: &str, now: NaiveDateTime) -> Result<bool, failure::Error> {
+        let since = now - Duration::minutes(STRIKE_WINDOW_MINUTES);
+        if self.strikes <= 0 || self.store.record(viber_id, now, since)? < self.strikes {
+            return Ok(false);
+        }
+        let until = now + Duration::minutes(self.block_minutes);
+        self.store.block(viber_id, until)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::FixedClock;
+    use testing::recorded;
+
+    // strikes, the last strike and the block of each user, as in `spam_strikes`
+    #[derive(Default)]
+    struct MemoryStrikes(Mutex<HashMap<String, (i32, NaiveDateTime, Option<NaiveDateTime>)>>);
+
+    impl StrikeStore for MemoryStrikes {
+        fn blocked_until(&self, viber_id: &str) -> Result<Option<NaiveDateTime>, failure::Error> {
+            Ok(self.0.lock().unwrap().get(viber_id).and_then(|s| s.2))
+        }
+
+        fn record(
+            &self,
+            viber_id: &str,
+            now: NaiveDateTime,
+            since: NaiveDateTime,
+        ) -> Result<i32, failure::Error> {
+            let mut strikes = self.0.lock().unwrap();
+            let entry = strikes.entry(viber_id.to_owned()).or_insert((0, now, None));
+            entry.0 = if entry.1 >= since { entry.0 + 1 } else { 1 };
+            entry.1 = now;
+            Ok(entry.0)
+        }
+
+        fn block(&self, viber_id: &str, until: NaiveDateTime) -> Result<(), failure::Error> {
+            if let Some(entry) = self.0.lock().unwrap().get_mut(viber_id) {
+                entry.0 = 0;
+                entry.2 = Some(until);
+            }
+            Ok(())
+        }
+
+        fn unblock(&self, viber_id: &str, now: NaiveDateTime) -> Result<bool, failure::Error> {
+            let removed = self.0.lock().unwrap().remove(viber_id);
+            Ok(removed.and_then(|s| s.2).map_or(false, |until| until > now))
+        }
+    }
+
+    // 10 messages in a row, one more every 10 seconds, blocked for an hour on the third cooldown
+    fn limiter() -> (RateLimiter, Arc<FixedClock>) {
+        let config: Config = ::serde_json::from_str(r#"{"admin_id": "admin"}"#).unwrap();
+        let clock = Arc::new(FixedClock::new(recorded()));
+        let limiter =
+            RateLimiter::with_store(&config, Box::new(MemoryStrikes::default()), clock.clone());
+        (limiter, clock)
+    }
+
+    fn spend(limiter: &RateLimiter, messages: usize) {
+        for i in 0..messages {
+            assert_eq!(limiter.check("user"), Verdict::Allow, "message {}", i);
+        }
+    }
+
+    #[test]
+    fn burst_and_refill() {
+        let (limiter, clock) = limiter();
+        spend(&limiter, 10);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+        // half a message later
+        clock.advance(Duration::seconds(5));
+        assert_eq!(limiter.check("user"), Verdict::Drop);
+        clock.advance(Duration::seconds(5));
+        spend(&limiter, 1);
+        clock.advance(Duration::seconds(25));
+        spend(&limiter, 2);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(5));
+        // no more than the burst is saved up
+        clock.advance(Duration::days(1));
+        spend(&limiter, 10);
+        assert_ne!(limiter.check("user"), Verdict::Allow);
+        // others have their own buckets
+        assert_eq!(limiter.check("other"), Verdict::Allow);
+        assert_eq!(limiter.check("admin"), Verdict::Allow);
+    }
+
+    #[test]
+    fn notice_once() {
+        let (limiter, clock) = limiter();
+        spend(&limiter, 10);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+        for _ in 0..5 {
+            assert_eq!(limiter.check("user"), Verdict::Drop);
+        }
+        // told again only after a message got through
+        clock.advance(Duration::seconds(10));
+        spend(&limiter, 1);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+        assert_eq!(limiter.check("user"), Verdict::Drop);
+    }
+
+    #[test]
+    fn strikes_to_block() {
+        let (limiter, clock) = limiter();
+        spend(&limiter, 10);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+        clock.advance(Duration::seconds(10));
+        spend(&limiter, 1);
+        assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+        assert_eq!(limiter.store.blocked_until("user").unwrap(), None);
+        clock.advance(Duration::seconds(10));
+        spend(&limiter, 1);
+        assert_eq!(limiter.check("user"), Verdict::Blocked(60));
+        clock.advance(Duration::minutes(59));
+        assert_eq!(limiter.check("user"), Verdict::Drop);
+        clock.advance(Duration::minutes(1));
+        assert_eq!(limiter.check("user"), Verdict::Allow);
+    }
+
+    #[test]
+    fn strikes_far_apart() {
+        let (limiter, clock) = limiter();
+        for _ in 0..5 {
+            spend(&limiter, 10);
+            assert_eq!(limiter.check("user"), Verdict::Cooldown(10));
+            clock.advance(Duration::minutes(STRIKE_WINDOW_MINUTES + 1));
+        }
+    }
+
+    #[test]
+    fn unblock() {
+        let (limiter, clock) = limiter();
+        for _ in 0..3 {
+            spend(&limiter, 10);
+            limiter.check("user");
+            clock.advance(Duration::seconds(100));
+        }
+        assert_eq!(limiter.check("user"), Verdict::Drop);
+        assert!(limiter.unblock("user").unwrap());
+        spend(&limiter, 10);
+        assert!(!limiter.unblock("user").unwrap());
+    }
+}
@@ -1,28 +1,532 @@
 use chrono::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::{PgConnection, QueryResult};
+use models::ScheduledJob;
+use std::collections::VecDeque;
 
-pub struct TryTillSuccess {
+pub mod schedule;
+
+pub use self::schedule::{Misfire, Schedule};
+
+static NEVER: i64 = i64::max_value();
+
+fn to_timestamp(time: Option<NaiveDateTime>) -> i64 {
+    time.map(|t| t.timestamp()).unwrap_or(0)
+}
+
+fn to_time(timestamp: i64) -> Option<NaiveDateTime> {
+    if timestamp == 0 || timestamp == NEVER {
+        None
+    } else {
+        Some(NaiveDateTime::from_timestamp(timestamp, 0))
+    }
+}
+
+// saved in `scheduled_jobs`, so that a job done today isn't repeated after a restart
+pub struct JobState {
+    pub name: &'static str,
+    pub last_run: i64,
     pub last_success: i64,
+    pub next_due: i64,
+    pub failures: i32,
+    pub paused: bool,
+}
+
+impl JobState {
+    pub fn new(name: &'static str) -> JobState {
+        JobState {
+            name,
+            last_run: 0,
+            last_success: 0,
+            next_due: 0,
+            failures: 0,
+            paused: false,
+        }
+    }
+
+    pub fn find(name: &'static str, conn: &PgConnection) -> QueryResult<Option<JobState>> {
+        Ok(ScheduledJob::find(name, conn)?.map(|job| JobState {
+            name,
+            last_run: to_timestamp(job.last_run),
+            last_success: to_timestamp(job.last_success),
+            next_due: to_timestamp(job.next_due),
+            failures: job.failures,
+            paused: job.paused,
+        }))
+    }
+
+    pub fn load(name: &'static str, pool: &Pool<ConnectionManager<PgConnection>>) -> JobState {
+        let job = pool
+            .get()
+            .map_err(|e| failure::Error::from(e))
+            .and_then(|conn| JobState::find(name, &conn).map_err(|e| failure::Error::from(e)));
+        match job {
+            Ok(Some(job)) => job,
+            Ok(None) => JobState::new(name),
+            Err(e) => {
+                error!("Can't load the state of the {} job. {:?}", name, e);
+                JobState::new(name)
+            }
+        }
+    }
+
+    pub fn save(&self, conn: &PgConnection) -> QueryResult<usize> {
+        ScheduledJob {
+            name: self.name.to_owned(),
+            last_run: to_time(self.last_run),
+            last_success: to_time(self.last_success),
+            next_due: to_time(self.next_due),
+            failures: self.failures,
+            paused: self.paused,
+        }
+        .upsert(conn)
+    }
+
+    pub fn record(&mut self, now: i64, success: bool) {
+        self.last_run = now;
+        if success {
+            self.last_success = now;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+        }
+    }
+}
+
+// a run later than this counts as missed and goes by the misfire policy
+static MISFIRE_GRACE_SECONDS: i64 = 5 * 60;
+// missed runs caught up at most, the older ones are skipped
+pub static MAX_CATCH_UP: usize = 24;
+// failed runs are retried after this, unless the next run comes sooner
+static RETRY_SECONDS: i64 = 10 * 60;
+// failures in a row after which the job waits for the next regular run
+static MAX_RETRIES: i32 = 3;
+
+pub struct Job {
+    pub name: &'static str,
+    pub schedule_text: String,
+    pub schedule: Schedule,
+    pub misfire: Misfire,
+    pub state: JobState,
 }
 
-impl TryTillSuccess {
-    pub fn new() -> TryTillSuccess {
-        TryTillSuccess { last_success: 0 }
+impl Job {
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        if self.state.paused || self.state.next_due == NEVER {
+            None
+        } else {
+            Some(Utc.timestamp(self.state.next_due, 0))
+        }
+    }
+
+    fn next_after(&self, after: i64) -> i64 {
+        self.schedule
+            .next_after(Utc.timestamp(after, 0))
+            .map(|time| time.timestamp())
+            .unwrap_or(NEVER)
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    jobs: Vec<Job>,
+}
+
+impl Registry {
+    // the saved next run time is kept unless the schedule, e.g. a changed one, comes sooner
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        schedule_text: &str,
+        schedule: Schedule,
+        misfire: Misfire,
+        pool: &Pool<ConnectionManager<PgConnection>>,
+        now: i64,
+    ) {
+        let mut job = Job {
+            name,
+            schedule_text: schedule_text.to_owned(),
+            schedule,
+            misfire,
+            state: JobState::load(name, pool),
+        };
+        let next = job.next_after(now);
+        if job.state.next_due == 0 || job.state.next_due > next {
+            job.state.next_due = next;
+        }
+        self.jobs.push(job);
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
     }
 
-    pub fn daily(&mut self, from: u8, to: u8, f: &mut FnMut() -> bool) -> &Self {
-        let now = Utc::now();
-        let past_event = Utc.timestamp(self.last_success, 0);
-        let lower_edge = Utc::now().with_hour(from as u32).unwrap();
-        if lower_edge.timestamp() - past_event.timestamp()
-            > ((24 - to as i64 + from as i64) * 60 * 60)
-            && now.hour() < to as u32
-            && now.hour() > from as u32
-        {
-            if f() {
-                self.last_success = now.timestamp();
+    pub fn get(&self, name: &str) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.name == name)
+    }
+
+    pub fn last_success(&self, name: &str) -> i64 {
+        self.get(name)
+            .map(|job| job.state.last_success)
+            .unwrap_or(0)
+    }
+
+    // the state saved by another instance, keeping the own next run time if none is saved
+    pub fn reload(&mut self, name: &str, conn: &PgConnection) -> QueryResult<()> {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.name == name) {
+            if let Some(mut state) = JobState::find(job.name, conn)? {
+                if state.next_due == 0 {
+                    state.next_due = job.state.next_due;
+                }
+                job.state = state;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn due(&mut self, now: i64) -> Vec<&'static str> {
+        let mut due = Vec::new();
+        for job in &mut self.jobs {
+            if job.state.paused || job.state.next_due > now {
+                continue;
+            }
+            if now - job.state.next_due > MISFIRE_GRACE_SECONDS {
+                match job.misfire {
+                    Misfire::Skip => {
+                        info!("Skipping the missed runs of the {} job.", job.name);
+                        job.state.next_due = job.next_after(now);
+                        continue;
+                    }
+                    Misfire::RunOnce => {}
+                    Misfire::CatchUp => {
+                        // the latest missed times
+                        let mut missed = VecDeque::new();
+                        let mut time = job.state.next_due;
+                        while time <= now {
+                            missed.push_back(time);
+                            if missed.len() > MAX_CATCH_UP {
+                                missed.pop_front();
+                            }
+                            time = job.next_after(time);
+                        }
+                        job.state.next_due = missed[0];
+                    }
+                }
             }
-            // check if success -> change past event
+            due.push(job.name);
         }
-        self
+        due
+    }
+
+    pub fn record(&mut self, name: &str, now: i64, success: bool) -> Option<&JobState> {
+        let job = self.jobs.iter_mut().find(|job| job.name == name)?;
+        job.state.record(now, success);
+        Some(&job.state)
+    }
+
+    pub fn set_paused(&mut self, name: &str, paused: bool) -> Option<&JobState> {
+        let job = self.jobs.iter_mut().find(|job| job.name == name)?;
+        job.state.paused = paused;
+        Some(&job.state)
+    }
+
+    pub fn finish(&mut self, name: &str, now: i64, success: bool) -> Option<&JobState> {
+        let job = self.jobs.iter_mut().find(|job| job.name == name)?;
+        job.state.record(now, success);
+        let scheduled = job.state.next_due;
+        let next = if job.misfire == Misfire::CatchUp {
+            // the next missed time, if any, runs on the next tick
+            job.next_after(scheduled)
+        } else {
+            job.next_after(now.max(scheduled))
+        };
+        job.state.next_due = if !success && job.state.failures <= MAX_RETRIES {
+            next.min(now + RETRY_SECONDS)
+        } else {
+            next
+        };
+        Some(&job.state)
+    }
+}
+
+static DELIVERY_GRACE_MINUTES: i64 = 15;
+
+pub fn parse_time_of_day(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M").ok()
+}
+
+pub fn time_to_minutes(time: &NaiveTime) -> i16 {
+    (time.hour() * 60 + time.minute()) as i16
+}
+
+pub fn minutes_to_time(minutes: i16) -> NaiveTime {
+    NaiveTime::from_hms((minutes / 60) as u32 % 24, (minutes % 60) as u32, 0)
+}
+
+// a time skipped when the clocks go forward moves to the first minute after the gap,
+// a time repeated when they go back is taken the first time
+fn delivery_instant<T: TimeZone>(tz: &T, date: NaiveDate, at: &NaiveTime) -> DateTime<Utc> {
+    let mut local = date.and_time(*at);
+    for _ in 0..24 * 60 {
+        if let Some(instant) = tz.from_local_datetime(&local).earliest() {
+            return instant.with_timezone(&Utc);
+        }
+        local = local + Duration::minutes(1);
+    }
+    Utc.from_utc_datetime(&date.and_time(*at))
+}
+
+// returns the local date the due delivery belongs to, which is yesterday when the grace
+// window runs past midnight. `last` is the date of the previous delivery, so each day gets
+// at most one
+pub fn daily_delivery_due<T: TimeZone>(
+    now: &DateTime<Utc>,
+    tz: &T,
+    at: &NaiveTime,
+    last: Option<NaiveDate>,
+) -> Option<NaiveDate> {
+    let today = now.with_timezone(tz).date().naive_local();
+    [today.pred(), today]
+        .iter()
+        .cloned()
+        .filter(|date| last.map_or(true, |last| last < *date))
+        .find(|date| {
+            let late = now
+                .signed_duration_since(delivery_instant(tz, *date, at))
+                .num_seconds();
+            late >= 0 && late < DELIVERY_GRACE_MINUTES * 60
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Kiev;
+
+    // 06:00 UTC on Monday, October 19
+    static T0: i64 = 1_792_389_600;
+    static HOUR: i64 = 60 * 60;
+
+    fn one_job(schedule: &str, misfire: Misfire, next_due: i64) -> Registry {
+        let mut state = JobState::new("job");
+        state.next_due = next_due;
+        Registry {
+            jobs: vec![Job {
+                name: "job",
+                schedule_text: schedule.to_owned(),
+                schedule: Schedule::parse(schedule, Kiev).unwrap(),
+                misfire,
+                state,
+            }],
+        }
+    }
+
+    fn state(registry: &Registry) -> &JobState {
+        &registry.get("job").unwrap().state
+    }
+
+    // runs the job successfully at `now` while it is due, returns the times it ran
+    fn run_due(registry: &mut Registry, now: i64) -> Vec<i64> {
+        let mut runs = Vec::new();
+        while registry.due(now) == vec!["job"] {
+            runs.push(state(registry).next_due);
+            registry.finish("job", now, true);
+            assert!(runs.len() <= MAX_CATCH_UP);
+        }
+        runs
+    }
+
+    #[test]
+    fn due_at_the_edges() {
+        for misfire in &[Misfire::Skip, Misfire::RunOnce, Misfire::CatchUp] {
+            let mut registry = one_job("every 1h", *misfire, T0);
+            assert!(registry.due(T0 - 1).is_empty());
+            assert_eq!(registry.due(T0), vec!["job"]);
+            assert_eq!(registry.due(T0 + MISFIRE_GRACE_SECONDS), vec!["job"]);
+        }
+        let mut registry = one_job("every 1h", Misfire::Skip, T0);
+        assert!(registry.due(T0 + MISFIRE_GRACE_SECONDS + 1).is_empty());
+        assert_eq!(
+            state(&registry).next_due,
+            T0 + MISFIRE_GRACE_SECONDS + 1 + HOUR
+        );
+    }
+
+    #[test]
+    fn misfire_policies() {
+        for late in (0..30 * HOUR).step_by(97) {
+            let now = T0 + late;
+            for misfire in &[Misfire::Skip, Misfire::RunOnce, Misfire::CatchUp] {
+                let mut registry = one_job("every 1h", *misfire, T0);
+                let runs = run_due(&mut registry, now);
+                let expected = match misfire {
+                    _ if late <= MISFIRE_GRACE_SECONDS => vec![T0],
+                    Misfire::Skip => vec![],
+                    Misfire::RunOnce => vec![T0],
+                    Misfire::CatchUp => {
+                        let missed = late / HOUR + 1;
+                        let skipped = (missed - MAX_CATCH_UP as i64).max(0);
+                        (skipped..missed).map(|i| T0 + i * HOUR).collect()
+                    }
+                };
+                assert_eq!(runs, expected, "{:?} {}s late", misfire, late);
+                assert!(
+                    state(&registry).next_due > now,
+                    "{:?} {}s late",
+                    misfire,
+                    late
+                );
+                assert!(
+                    state(&registry).next_due <= now + HOUR,
+                    "{:?} {}s late",
+                    misfire,
+                    late
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn catch_up_across_the_clock_changes() {
+        let utc = |month, day, hour, minute| {
+            Utc.ymd(2026, month, day)
+                .and_hms(hour, minute, 0)
+                .timestamp()
+        };
+        // 03:30 doesn't happen on March 29 in Kyiv
+        let mut registry = one_job("daily 3:30", Misfire::CatchUp, utc(3, 28, 1, 30));
+        assert_eq!(
+            run_due(&mut registry, utc(3, 31, 12, 0)),
+            vec![utc(3, 28, 1, 30), utc(3, 30, 0, 30), utc(3, 31, 0, 30)]
+        );
+        // 03:30 happens twice on October 25, the job runs once
+        let mut registry = one_job("daily 3:30", Misfire::CatchUp, utc(10, 24, 0, 30));
+        assert_eq!(
+            run_due(&mut registry, utc(10, 26, 12, 0)),
+            vec![utc(10, 24, 0, 30), utc(10, 25, 0, 30), utc(10, 26, 1, 30)]
+        );
+        assert_eq!(state(&registry).next_due, utc(10, 27, 1, 30));
+    }
+
+    #[test]
+    fn retries() {
+        let mut registry = one_job("every 1h", Misfire::Skip, T0);
+        let mut now = T0;
+        for failures in 1..=MAX_RETRIES {
+            assert_eq!(registry.due(now), vec!["job"]);
+            registry.finish("job", now, false);
+            assert_eq!(state(&registry).failures, failures);
+            assert_eq!(state(&registry).next_due, now + RETRY_SECONDS);
+            now += RETRY_SECONDS;
+        }
+        // too many failures in a row wait for the next regular run
+        registry.finish("job", now, false);
+        assert_eq!(state(&registry).next_due, now + HOUR);
+        now += HOUR;
+        registry.finish("job", now, true);
+        assert_eq!(state(&registry).failures, 0);
+        assert_eq!(state(&registry).last_success, now);
+        // retries don't delay the next regular run
+        let mut registry = one_job("every 5m", Misfire::Skip, T0);
+        registry.finish("job", T0, false);
+        assert_eq!(state(&registry).next_due, T0 + 5 * 60);
+    }
+
+    fn delivery(now: DateTime<Utc>, at: &str, last: Option<NaiveDate>) -> Option<NaiveDate> {
+        daily_delivery_due(&now, &Kiev, &parse_time_of_day(at).unwrap(), last)
+    }
+
+    #[test]
+    fn delivery_grace_window() {
+        let monday = NaiveDate::from_ymd(2026, 10, 19);
+        let at = |hour, minute, second| Kiev.ymd(2026, 10, 19).and_hms(hour, minute, second);
+        assert_eq!(
+            delivery(at(7, 59, 59).with_timezone(&Utc), "8:00", None),
+            None
+        );
+        assert_eq!(
+            delivery(at(8, 0, 0).with_timezone(&Utc), "8:00", None),
+            Some(monday)
+        );
+        assert_eq!(
+            delivery(at(8, 14, 59).with_timezone(&Utc), "8:00", None),
+            Some(monday)
+        );
+        assert_eq!(
+            delivery(at(8, 15, 0).with_timezone(&Utc), "8:00", None),
+            None
+        );
+        // once a day
+        let sunday = monday.pred();
+        assert_eq!(
+            delivery(at(8, 5, 0).with_timezone(&Utc), "8:00", Some(sunday)),
+            Some(monday)
+        );
+        assert_eq!(
+            delivery(at(8, 5, 0).with_timezone(&Utc), "8:00", Some(monday)),
+            None
+        );
+    }
+
+    #[test]
+    fn delivery_past_midnight() {
+        let monday = NaiveDate::from_ymd(2026, 10, 19);
+        let tuesday = monday.succ();
+        let at = |day, hour, minute| Kiev.ymd(2026, 10, day).and_hms(hour, minute, 0);
+        assert_eq!(
+            delivery(at(19, 23, 55).with_timezone(&Utc), "23:55", None),
+            Some(monday)
+        );
+        // the window of Monday's delivery ends on Tuesday
+        assert_eq!(
+            delivery(at(20, 0, 5).with_timezone(&Utc), "23:55", None),
+            Some(monday)
+        );
+        assert_eq!(
+            delivery(at(20, 0, 5).with_timezone(&Utc), "23:55", Some(monday)),
+            None
+        );
+        assert_eq!(
+            delivery(at(20, 0, 10).with_timezone(&Utc), "23:55", None),
+            None
+        );
+        // a delivery on Monday evening doesn't stop the one on Tuesday evening
+        assert_eq!(
+            delivery(at(20, 23, 56).with_timezone(&Utc), "23:55", Some(monday)),
+            Some(tuesday)
+        );
+        assert_eq!(
+            delivery(at(20, 0, 0).with_timezone(&Utc), "0:00", None),
+            Some(tuesday)
+        );
+    }
+
+    #[test]
+    fn delivery_across_the_clock_changes() {
+        let utc = |month, day, hour, minute| Utc.ymd(2026, month, day).and_hms(hour, minute, 0);
+        // 03:30 doesn't happen on March 29 in Kyiv, the delivery comes at 04:00 instead
+        let spring = NaiveDate::from_ymd(2026, 3, 29);
+        assert_eq!(delivery(utc(3, 29, 0, 59), "3:30", None), None);
+        assert_eq!(delivery(utc(3, 29, 1, 0), "3:30", None), Some(spring));
+        assert_eq!(delivery(utc(3, 29, 1, 14), "3:30", None), Some(spring));
+        assert_eq!(delivery(utc(3, 29, 1, 15), "3:30", None), None);
+        // 03:30 happens twice on October 25, the delivery comes the first time only
+        let autumn = NaiveDate::from_ymd(2026, 10, 25);
+        assert_eq!(delivery(utc(10, 25, 0, 30), "3:30", None), Some(autumn));
+        assert_eq!(delivery(utc(10, 25, 1, 30), "3:30", None), None);
+        assert_eq!(delivery(utc(10, 25, 1, 30), "3:30", Some(autumn)), None);
+    }
+
+    #[test]
+    fn paused() {
+        let mut registry = one_job("every 1h", Misfire::RunOnce, T0);
+        registry.set_paused("job", true);
+        assert!(registry.due(T0 + 10 * HOUR).is_empty());
+        assert!(registry.get("job").unwrap().next_run().is_none());
+        // triggered by hand, the schedule is kept
+        registry.record("job", T0 + 10 * HOUR, true);
+        assert_eq!(state(&registry).next_due, T0);
+        registry.set_paused("job", false);
+        assert_eq!(run_due(&mut registry, T0 + 10 * HOUR), vec![T0]);
     }
 }
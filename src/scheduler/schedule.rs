@@ -0,0 +1,446 @@
+use chrono::*;
+use chrono_tz::Tz;
+use scheduler::parse_time_of_day;
+
+// enough for "0 0 29 2 *"
+static CRON_SEARCH_DAYS: i64 = 366 * 4 + 1;
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Every(Duration),
+    Daily {
+        at: NaiveTime,
+        tz: Tz,
+    },
+    Weekly {
+        weekday: Weekday,
+        at: NaiveTime,
+        tz: Tz,
+    },
+    Cron {
+        cron: Cron,
+        tz: Tz,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misfire {
+    Skip,
+    RunOnce,
+    CatchUp,
+}
+
+impl Misfire {
+    pub fn from_code(code: &str) -> Option<Misfire> {
+        match code.trim() {
+            "skip" => Some(Misfire::Skip),
+            "run_once" => Some(Misfire::RunOnce),
+            "catch_up" => Some(Misfire::CatchUp),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Misfire::Skip => "skip",
+            Misfire::RunOnce => "run_once",
+            Misfire::CatchUp => "catch_up",
+        }
+    }
+}
+
+fn parse_weekday(text: &str) -> Option<Weekday> {
+    match text.trim().to_lowercase().as_str() {
+        "mon" => Some(Weekday::Mon),
+        "tue" => Some(Weekday::Tue),
+        "wed" => Some(Weekday::Wed),
+        "thu" => Some(Weekday::Thu),
+        "fri" => Some(Weekday::Fri),
+        "sat" => Some(Weekday::Sat),
+        "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if text.len() < 2 {
+        return None;
+    }
+    let (number, unit) = text.split_at(text.len() - 1);
+    let number: i64 = number.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "s" => Some(Duration::seconds(number)),
+        "m" => Some(Duration::minutes(number)),
+        "h" => Some(Duration::hours(number)),
+        "d" => Some(Duration::days(number)),
+        _ => None,
+    }
+}
+
+// None when the clocks skip the local time
+fn at_local(tz: &Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+impl Schedule {
+    pub fn parse(text: &str, tz: Tz) -> Option<Schedule> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        match parts.as_slice() {
+            ["every", duration] => parse_duration(duration).map(Schedule::Every),
+            ["daily", at] => parse_time_of_day(at).map(|at| Schedule::Daily { at, tz }),
+            ["weekly", weekday, at] => Some(Schedule::Weekly {
+                weekday: parse_weekday(weekday)?,
+                at: parse_time_of_day(at)?,
+                tz,
+            }),
+            _ => Cron::parse(text).map(|cron| Schedule::Cron { cron, tz }),
+        }
+    }
+
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => Some(after + *interval),
+            Schedule::Daily { at, tz } => {
+                let today = after.with_timezone(tz).naive_local().date();
+                (0..3)
+                    .filter_map(|days| at_local(tz, today + Duration::days(days), *at))
+                    .find(|time| *time > after)
+            }
+            Schedule::Weekly { weekday, at, tz } => {
+                let today = after.with_timezone(tz).naive_local().date();
+                (0..15)
+                    .map(|days| today + Duration::days(days))
+                    .filter(|date| date.weekday() == *weekday)
+                    .filter_map(|date| at_local(tz, date, *at))
+                    .find(|time| *time > after)
+            }
+            Schedule::Cron { cron, tz } => cron.next_after(after, tz),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    // 0 is Sunday
+    weekdays: Vec<u32>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Option<Vec<u32>> {
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => (&part[..i], part[i + 1..].parse().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else {
+            match range.find('-') {
+                Some(i) => (range[..i].parse().ok()?, range[i + 1..].parse().ok()?),
+                None => {
+                    let value = range.parse().ok()?;
+                    (value, if part.contains('/') { max } else { value })
+                }
+            }
+        };
+        if from < min || to > max || from > to {
+            return None;
+        }
+        values.extend((from..=to).step_by(step));
+    }
+    values.sort();
+    values.dedup();
+    Some(values)
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Option<Cron> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let mut weekdays: Vec<u32> = parse_field(fields[4], 0, 7)?
+            .into_iter()
+            .map(|d| d % 7)
+            .collect();
+        weekdays.sort();
+        weekdays.dedup();
+        Some(Cron {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    // the day of month and the day of week are alternatives when both are set
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(&date.month()) {
+            return false;
+        }
+        let day = self.days.contains(&date.day());
+        let weekday = self
+            .weekdays
+            .contains(&date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+
+    fn next_after(&self, after: DateTime<Utc>, tz: &Tz) -> Option<DateTime<Utc>> {
+        let today = after.with_timezone(tz).naive_local().date();
+        for days in 0..CRON_SEARCH_DAYS {
+            let date = today + Duration::days(days);
+            if !self.matches_date(date) {
+                continue;
+            }
+            for hour in &self.hours {
+                for minute in &self.minutes {
+                    let time = NaiveTime::from_hms(*hour, *minute, 0);
+                    match at_local(tz, date, time) {
+                        Some(time) if time > after => return Some(time),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Kiev;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(year, month, day).and_hms(hour, minute, 0)
+    }
+
+    fn cron(text: &str) -> Schedule {
+        Schedule::parse(text, Kiev).unwrap()
+    }
+
+    // times through 2026, both clock changes in Kyiv included
+    fn samples() -> Vec<DateTime<Utc>> {
+        let start = utc(2026, 1, 1, 0, 0);
+        let mut samples: Vec<_> = (0..1200)
+            .map(|i| start + Duration::minutes(i * 439))
+            .collect();
+        for change in &[utc(2026, 3, 29, 1, 0), utc(2026, 10, 25, 1, 0)] {
+            samples.extend((-30..30).map(|i| *change + Duration::minutes(i * 7)));
+        }
+        samples
+    }
+
+    // whether the cron runs at the minute, which must be the first of its local time
+    fn runs_at(cron: &Cron, time: DateTime<Utc>) -> bool {
+        let local = time.with_timezone(&Kiev).naive_local();
+        cron.minutes.contains(&local.minute())
+            && cron.hours.contains(&local.hour())
+            && cron.matches_date(local.date())
+            && Kiev.from_local_datetime(&local).earliest() == Some(time.with_timezone(&Kiev))
+    }
+
+    #[test]
+    fn parse_fields() {
+        let cron = Cron::parse("0-30/10 9-17 1,15 */3 1-5").unwrap();
+        assert_eq!(cron.minutes, vec![0, 10, 20, 30]);
+        assert_eq!(cron.hours, (9..18).collect::<Vec<_>>());
+        assert_eq!(cron.days, vec![1, 15]);
+        assert_eq!(cron.months, vec![1, 4, 7, 10]);
+        assert_eq!(cron.weekdays, vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            Cron::parse("5/20 * * * *").unwrap().minutes,
+            vec![5, 25, 45]
+        );
+        // both 0 and 7 are Sunday
+        assert_eq!(Cron::parse("0 0 * * 5-7").unwrap().weekdays, vec![0, 5, 6]);
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for text in &[
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+        ] {
+            assert!(Cron::parse(text).is_none(), "{:?}", text);
+        }
+        assert!(Schedule::parse("every 0m", Kiev).is_none());
+        assert!(Schedule::parse("every 10", Kiev).is_none());
+        assert!(Schedule::parse("daily 25:00", Kiev).is_none());
+        assert!(Schedule::parse("weekly monday 7:30", Kiev).is_none());
+    }
+
+    #[test]
+    fn window_edges() {
+        let schedule = cron("0-30/10 9-17 * * 1-5");
+        // 09:00 on Monday, October 19, in Kyiv
+        let monday = utc(2026, 10, 19, 6, 0);
+        assert_eq!(
+            schedule.next_after(monday - Duration::seconds(1)),
+            Some(monday)
+        );
+        assert_eq!(
+            schedule.next_after(monday),
+            Some(monday + Duration::minutes(10))
+        );
+        // from the last run of the window to the first of the next one
+        assert_eq!(
+            schedule.next_after(monday + Duration::minutes(8 * 60 + 30)),
+            Some(monday + Duration::days(1))
+        );
+        // from the last run of the week to Monday, after the clocks went back
+        let friday = monday + Duration::days(4);
+        assert_eq!(
+            schedule.next_after(friday + Duration::minutes(8 * 60 + 30)),
+            Some(utc(2026, 10, 26, 7, 0))
+        );
+    }
+
+    #[test]
+    fn day_of_month_or_weekday() {
+        // the 13th or a Friday, at noon in Kyiv
+        let schedule = cron("0 12 13 * 5");
+        let mut time = utc(2026, 11, 1, 0, 0);
+        let mut runs = Vec::new();
+        for _ in 0..5 {
+            time = schedule.next_after(time).unwrap();
+            runs.push(time.with_timezone(&Kiev).day());
+        }
+        assert_eq!(runs, vec![6, 13, 20, 27, 4]);
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(
+            cron("0 0 29 2 *").next_after(utc(2026, 3, 1, 0, 0)),
+            Some(utc(2028, 2, 28, 22, 0))
+        );
+        assert_eq!(cron("0 0 31 2 *").next_after(utc(2026, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn spring_forward() {
+        // 03:30 doesn't happen on March 29 in Kyiv
+        let after = utc(2026, 3, 28, 12, 0);
+        let next = Some(utc(2026, 3, 30, 0, 30));
+        assert_eq!(cron("30 3 * * *").next_after(after), next);
+        assert_eq!(cron("daily 3:30").next_after(after), next);
+        assert_eq!(
+            cron("weekly sun 3:30").next_after(after),
+            Some(utc(2026, 4, 5, 0, 30))
+        );
+        // 02:30 does, an hour before the change
+        assert_eq!(
+            cron("daily 2:30").next_after(after),
+            Some(utc(2026, 3, 29, 0, 30))
+        );
+    }
+
+    #[test]
+    fn fall_back() {
+        // 03:30 happens twice on October 25 in Kyiv, the run is at the first one
+        let first = utc(2026, 10, 25, 0, 30);
+        for schedule in &[
+            cron("30 3 * * *"),
+            cron("daily 3:30"),
+            cron("weekly sun 3:30"),
+        ] {
+            assert_eq!(
+                schedule.next_after(first - Duration::hours(12)),
+                Some(first)
+            );
+        }
+        let next = Some(utc(2026, 10, 26, 1, 30));
+        assert_eq!(cron("30 3 * * *").next_after(first), next);
+        assert_eq!(cron("daily 3:30").next_after(first), next);
+        assert_eq!(
+            cron("weekly sun 3:30").next_after(first),
+            Some(utc(2026, 11, 1, 1, 30))
+        );
+    }
+
+    #[test]
+    fn every() {
+        let after = utc(2026, 3, 29, 0, 30);
+        assert_eq!(
+            cron("every 90s").next_after(after),
+            Some(after + Duration::seconds(90))
+        );
+        // intervals don't care about the clock changes
+        assert_eq!(
+            cron("every 1d").next_after(after),
+            Some(after + Duration::hours(24))
+        );
+    }
+
+    #[test]
+    fn cron_next_is_the_first_run_after() {
+        for text in &[
+            "30 3 * * *",
+            "*/15 9-17 * * 1-5",
+            "0,30 * * * *",
+            "0 0-6/2 * * *",
+        ] {
+            let schedule = Cron::parse(text).unwrap();
+            for after in samples() {
+                let next = schedule.next_after(after, &Kiev).unwrap();
+                assert!(next > after, "{} after {}", text, after);
+                assert_eq!(next.second(), 0, "{} after {}", text, after);
+                assert!(runs_at(&schedule, next), "{} after {}", text, after);
+                let mut time = after + Duration::minutes(1);
+                while time < next {
+                    assert!(!runs_at(&schedule, time), "{} skips {}", text, time);
+                    time = time + Duration::minutes(1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn daily_and_weekly_as_cron() {
+        for (schedule, text) in &[
+            ("daily 3:30", "30 3 * * *"),
+            ("daily 0:00", "0 0 * * *"),
+            ("weekly sun 3:30", "30 3 * * 0"),
+            ("weekly fri 23:59", "59 23 * * 5"),
+        ] {
+            let (schedule, same) = (cron(schedule), cron(text));
+            for after in samples() {
+                assert_eq!(
+                    schedule.next_after(after),
+                    same.next_after(after),
+                    "{} after {}",
+                    text,
+                    after
+                );
+            }
+        }
+    }
+}
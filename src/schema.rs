@@ -1,10 +1,147 @@
+table! {
+    alert_rules (id) {
+        id -> Int4,
+        user_id -> Int4,
+        field -> Varchar,
+        op -> Varchar,
+        value -> Float8,
+        last_triggered -> Nullable<Date>,
+        units -> Varchar,
+    }
+}
+
+table! {
+    api_calls (id) {
+        id -> Int4,
+        provider -> Varchar,
+        endpoint -> Varchar,
+        latency_ms -> Int4,
+        success -> Bool,
+        called_at -> Timestamp,
+    }
+}
+
+table! {
+    api_usage (provider, day) {
+        provider -> Varchar,
+        day -> Date,
+        calls -> Int4,
+        warned -> Bool,
+    }
+}
+
+table! {
+    forecast_cache (lat_key, lon_key, lang) {
+        lat_key -> Int4,
+        lon_key -> Int4,
+        lang -> Varchar,
+        data -> Text,
+        fetched_at -> Timestamp,
+    }
+}
+
+table! {
+    job_leases (name) {
+        name -> Varchar,
+        holder -> Varchar,
+        expires -> Timestamp,
+    }
+}
+
+table! {
+    job_runs (id) {
+        id -> Int4,
+        job -> Varchar,
+        started -> Timestamp,
+        finished -> Timestamp,
+        success -> Bool,
+        error -> Nullable<Text>,
+        messages_sent -> Int4,
+    }
+}
+
+table! {
+    subscribers (viber_id) {
+        viber_id -> Varchar,
+        name -> Varchar,
+        avatar -> Nullable<Varchar>,
+        role -> Varchar,
+    }
+}
+
 table! {
     users (id) {
         id -> Int4,
         email -> Nullable<Varchar>,
         viber_id -> Nullable<Varchar>,
         broadcast -> Bool,
+        notify_time -> Nullable<Int2>,
+        timezone -> Varchar,
+        last_notified -> Nullable<Date>,
+        language -> Nullable<Varchar>,
+        units -> Nullable<Varchar>,
+        latitude -> Nullable<Float8>,
+        longitude -> Nullable<Float8>,
+        rain_alerts -> Bool,
+        last_rain_alert -> Nullable<Timestamp>,
+        forecast_fields -> Nullable<Varchar>,
+        link_code -> Nullable<Varchar>,
+        link_code_expires -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    quota_usage (id) {
+        id -> Int4,
+        viber_id -> Varchar,
+        command -> Varchar,
+        used_at -> Timestamp,
+    }
+}
+
+table! {
+    scheduled_jobs (name) {
+        name -> Varchar,
+        last_run -> Nullable<Timestamp>,
+        last_success -> Nullable<Timestamp>,
+        next_due -> Nullable<Timestamp>,
+        failures -> Int4,
+        paused -> Bool,
+    }
+}
+
+table! {
+    sent_alerts (id) {
+        id -> Int4,
+        viber_id -> Varchar,
+        alert_id -> Varchar,
+        expires -> Timestamp,
+        sent_at -> Timestamp,
+    }
+}
+
+joinable!(alert_rules -> users (user_id));
+
+table! {
+    spam_strikes (viber_id) {
+        viber_id -> Varchar,
+        strikes -> Int4,
+        last_strike -> Timestamp,
+        blocked_until -> Nullable<Timestamp>,
     }
 }
 
-allow_tables_to_appear_in_same_query!(users,);
+allow_tables_to_appear_in_same_query!(
+    alert_rules,
+    api_calls,
+    api_usage,
+    forecast_cache,
+    job_leases,
+    job_runs,
+    quota_usage,
+    scheduled_jobs,
+    sent_alerts,
+    spam_strikes,
+    subscribers,
+    users,
+);
@@ -0,0 +1,30 @@
+use chrono::{DateTime, TimeZone, Utc};
+use i18n::{Catalog, Lang};
+use units::UnitSystem;
+use weather::fixture::FixtureProvider;
+use weather::{Forecast, WeatherProvider};
+use workers::Prefs;
+
+// fixtures/default.json was recorded at 09:00 on Monday, October 19, in Kyiv
+pub fn recorded() -> DateTime<Utc> {
+    Utc.timestamp(1_792_389_600, 0)
+}
+
+pub fn fixture() -> Forecast {
+    FixtureProvider::new("fixtures")
+        .forecast(50.45, 30.52, Lang::En)
+        .unwrap()
+}
+
+pub fn catalog() -> Catalog {
+    Catalog::load("locales").unwrap()
+}
+
+// English and SI, without extra lines
+pub fn prefs() -> Prefs {
+    Prefs {
+        lang: Lang::En,
+        units: UnitSystem::Si,
+        fields: Vec::new(),
+    }
+}
@@ -0,0 +1,191 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UnitSystem {
+    // ℃, m/s, mm, km
+    Si,
+    // ℉, mph, inches, miles
+    Us,
+    // SI with mph and miles
+    Uk,
+    // SI with km/h
+    Ca,
+}
+
+impl Default for UnitSystem {
+    fn default() -> Self {
+        UnitSystem::Uk
+    }
+}
+
+static KMH_IN_MS: f64 = 3.6;
+static MPH_IN_MS: f64 = 2.236_936;
+static MM_IN_INCH: f64 = 25.4;
+static KM_IN_MILE: f64 = 1.609_344;
+
+impl UnitSystem {
+    pub fn all() -> &'static [UnitSystem] {
+        &[
+            UnitSystem::Si,
+            UnitSystem::Us,
+            UnitSystem::Uk,
+            UnitSystem::Ca,
+        ]
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            UnitSystem::Si => "si",
+            UnitSystem::Us => "us",
+            UnitSystem::Uk => "uk",
+            UnitSystem::Ca => "ca",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<UnitSystem> {
+        match code.trim().to_lowercase().as_str() {
+            "si" => Some(UnitSystem::Si),
+            "us" => Some(UnitSystem::Us),
+            "uk" | "uk2" => Some(UnitSystem::Uk),
+            "ca" => Some(UnitSystem::Ca),
+            _ => None,
+        }
+    }
+
+    pub fn temperature_symbol(&self) -> &'static str {
+        match self {
+            UnitSystem::Us => "\u{2109}",
+            _ => "\u{2103}",
+        }
+    }
+
+    pub fn speed_symbol(&self) -> &'static str {
+        match self {
+            UnitSystem::Si => "m/s",
+            UnitSystem::Us | UnitSystem::Uk => "mph",
+            UnitSystem::Ca => "km/h",
+        }
+    }
+
+    pub fn precip_symbol(&self) -> &'static str {
+        match self {
+            UnitSystem::Us => "in/h",
+            _ => "mm/h",
+        }
+    }
+
+    pub fn distance_symbol(&self) -> &'static str {
+        match self {
+            UnitSystem::Us | UnitSystem::Uk => "mi",
+            _ => "km",
+        }
+    }
+
+    pub fn temperature(&self, value: f64, to: UnitSystem) -> f64 {
+        let celsius = match self {
+            UnitSystem::Us => (value - 32.0) * 5.0 / 9.0,
+            _ => value,
+        };
+        match to {
+            UnitSystem::Us => celsius * 9.0 / 5.0 + 32.0,
+            _ => celsius,
+        }
+    }
+
+    pub fn speed(&self, value: f64, to: UnitSystem) -> f64 {
+        let ms = match self {
+            UnitSystem::Si => value,
+            UnitSystem::Us | UnitSystem::Uk => value / MPH_IN_MS,
+            UnitSystem::Ca => value / KMH_IN_MS,
+        };
+        match to {
+            UnitSystem::Si => ms,
+            UnitSystem::Us | UnitSystem::Uk => ms * MPH_IN_MS,
+            UnitSystem::Ca => ms * KMH_IN_MS,
+        }
+    }
+
+    pub fn precip(&self, value: f64, to: UnitSystem) -> f64 {
+        let mm = match self {
+            UnitSystem::Us => value * MM_IN_INCH,
+            _ => value,
+        };
+        match to {
+            UnitSystem::Us => mm / MM_IN_INCH,
+            _ => mm,
+        }
+    }
+
+    pub fn distance(&self, value: f64, to: UnitSystem) -> f64 {
+        let km = match self {
+            UnitSystem::Us | UnitSystem::Uk => value * KM_IN_MILE,
+            _ => value,
+        };
+        match to {
+            UnitSystem::Us | UnitSystem::Uk => km / KM_IN_MILE,
+            _ => km,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn known_values() {
+        let (si, us, uk, ca) = (
+            UnitSystem::Si,
+            UnitSystem::Us,
+            UnitSystem::Uk,
+            UnitSystem::Ca,
+        );
+        assert_close(si.temperature(0.0, us), 32.0, 1e-9);
+        assert_close(si.temperature(100.0, us), 212.0, 1e-9);
+        assert_close(us.temperature(-40.0, si), -40.0, 1e-9);
+        assert_close(uk.temperature(21.5, ca), 21.5, 1e-9);
+        assert_close(si.speed(10.0, us), 22.37, 0.01);
+        assert_close(si.speed(10.0, uk), 22.37, 0.01);
+        assert_close(si.speed(10.0, ca), 36.0, 1e-9);
+        assert_close(ca.speed(36.0, uk), 22.37, 0.01);
+        assert_close(us.precip(1.0, si), 25.4, 1e-9);
+        assert_close(uk.precip(2.5, ca), 2.5, 1e-9);
+        assert_close(uk.distance(1.0, si), 1.609, 0.001);
+        assert_close(ca.distance(10.0, us), 6.214, 0.001);
+    }
+
+    #[test]
+    fn round_trips() {
+        for from in UnitSystem::all() {
+            for to in UnitSystem::all() {
+                for value in &[-30.0, 0.0, 0.3, 17.0, 42.5] {
+                    let (from, to, value) = (*from, *to, *value);
+                    assert_close(
+                        to.temperature(from.temperature(value, to), from),
+                        value,
+                        1e-9,
+                    );
+                    assert_close(to.speed(from.speed(value, to), from), value, 1e-9);
+                    assert_close(to.precip(from.precip(value, to), from), value, 1e-9);
+                    assert_close(to.distance(from.distance(value, to), from), value, 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn codes() {
+        for units in UnitSystem::all() {
+            assert_eq!(UnitSystem::from_code(units.code()), Some(*units));
+        }
+        assert_eq!(UnitSystem::from_code(" UK2 "), Some(UnitSystem::Uk));
+        assert_eq!(UnitSystem::from_code("metric"), None);
+    }
+}
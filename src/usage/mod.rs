@@ -0,0 +1,106 @@
+use chrono::Utc;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use models::{ApiUsage, NewApiCall};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+static WARNING_PERCENT: i32 = 80;
+
+#[derive(Debug, Fail)]
+#[fail(display = "Daily budget of {} is exhausted", provider)]
+pub struct BudgetExceeded {
+    pub provider: String,
+}
+
+pub struct BudgetWarning {
+    pub provider: String,
+    pub calls: i32,
+    pub budget: i32,
+}
+
+// budgets are calls per provider and UTC day
+pub struct UsageTracker {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    budgets: HashMap<String, i32>,
+    warnings: Mutex<Vec<BudgetWarning>>,
+}
+
+impl UsageTracker {
+    pub fn new(
+        pool: Pool<ConnectionManager<PgConnection>>,
+        budgets: HashMap<String, i32>,
+    ) -> UsageTracker {
+        UsageTracker {
+            pool,
+            budgets,
+            warnings: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn allows(&self, provider: &str) -> Result<bool, failure::Error> {
+        let budget = match self.budgets.get(provider) {
+            Some(budget) => *budget,
+            None => return Ok(true),
+        };
+        let conn = self.pool.get()?;
+        let today = Utc::now().naive_utc().date();
+        Ok(ApiUsage::find(provider, today, &conn)?
+            .map(|usage| usage.calls < budget)
+            .unwrap_or(true))
+    }
+
+    fn record(
+        &self,
+        provider: &str,
+        endpoint: &str,
+        latency_ms: i32,
+        success: bool,
+    ) -> Result<(), failure::Error> {
+        let conn = self.pool.get()?;
+        NewApiCall {
+            provider,
+            endpoint,
+            latency_ms,
+            success,
+        }
+        .insert(&conn)?;
+        let today = Utc::now().naive_utc().date();
+        let usage = ApiUsage::increment(provider, today, &conn)?;
+        if let Some(&budget) = self.budgets.get(provider) {
+            if !usage.warned && usage.calls * 100 >= budget * WARNING_PERCENT {
+                ApiUsage::set_warned(provider, today, &conn)?;
+                self.warnings.lock().unwrap().push(BudgetWarning {
+                    provider: provider.to_owned(),
+                    calls: usage.calls,
+                    budget,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn track<T, F>(&self, provider: &str, endpoint: &str, call: F) -> Result<T, failure::Error>
+    where
+        F: FnOnce() -> Result<T, failure::Error>,
+    {
+        if !self.allows(provider).unwrap_or(true) {
+            return Err(failure::Error::from(BudgetExceeded {
+                provider: provider.to_owned(),
+            }));
+        }
+        let started = Instant::now();
+        let result = call();
+        let elapsed = started.elapsed();
+        let latency_ms = elapsed.as_secs() as i32 * 1000 + elapsed.subsec_millis() as i32;
+        self.record(provider, endpoint, latency_ms, result.is_ok())
+            .map_err(|e| error!("Can't record a call to {}. {:?}", provider, e))
+            .unwrap_or_default();
+        result
+    }
+
+    pub fn take_warnings(&self) -> Vec<BudgetWarning> {
+        self.warnings.lock().unwrap().drain(..).collect()
+    }
+}
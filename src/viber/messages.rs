@@ -72,6 +72,8 @@ pub struct Button<'s> {
     pub text: Cow<'s, str>,
     #[serde(rename = "TextSize")]
     pub text_size: Cow<'s, str>,
+    #[serde(rename = "Columns", skip_serializing_if = "Option::is_none")]
+    pub columns: Option<u8>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -0,0 +1,319 @@
+use chrono::{Duration, NaiveDateTime};
+use clock::Clock;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use i18n::Lang;
+use models::CachedForecast;
+use serde_json;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use weather::*;
+
+// the language is left out for the providers which answer the same in any language
+type Key = (i64, i64, Lang);
+
+#[derive(Default)]
+struct InFlight {
+    result: Mutex<Option<Result<Forecast, String>>>,
+    done: Condvar,
+}
+
+// concurrent identical requests wait for one fetch, an outdated forecast is served
+// when the provider fails
+pub struct CachedProvider {
+    inner: Box<WeatherProvider>,
+    ttl: Duration,
+    pool: Option<Pool<ConnectionManager<PgConnection>>>,
+    clock: Arc<Clock>,
+    entries: Mutex<HashMap<Key, (NaiveDateTime, Forecast)>>,
+    in_flight: Mutex<HashMap<Key, Arc<InFlight>>>,
+}
+
+impl CachedProvider {
+    pub fn new(
+        inner: Box<WeatherProvider>,
+        ttl: Duration,
+        pool: Option<Pool<ConnectionManager<PgConnection>>>,
+        clock: Arc<Clock>,
+    ) -> CachedProvider {
+        CachedProvider {
+            inner,
+            ttl,
+            pool,
+            clock,
+            entries: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_fresh(&self, fetched_at: &NaiveDateTime) -> bool {
+        self.clock
+            .now()
+            .naive_utc()
+            .signed_duration_since(*fetched_at)
+            < self.ttl
+    }
+
+    fn cached(&self, key: &Key) -> Option<Forecast> {
+        if let Some((fetched_at, forecast)) = self.entries.lock().unwrap().get(key) {
+            if self.is_fresh(fetched_at) {
+                return Some(forecast.clone());
+            }
+        }
+        let stored = self.load(key)?;
+        if !self.is_fresh(&stored.fetched_at) {
+            return None;
+        }
+        let forecast: Forecast = serde_json::from_str(&stored.data).ok()?;
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(*key, (stored.fetched_at, forecast.clone()));
+        Some(forecast)
+    }
+
+    fn stale(&self, key: &Key) -> Option<Forecast> {
+        if let Some((_, forecast)) = self.entries.lock().unwrap().get(key) {
+            return Some(forecast.clone());
+        }
+        serde_json::from_str(&self.load(key)?.data).ok()
+    }
+
+    fn load(&self, key: &Key) -> Option<CachedForecast> {
+        let conn = self.pool.as_ref()?.get().ok()?;
+        CachedForecast::find(key.0 as i32, key.1 as i32, key.2.code(), &conn)
+            .map_err(|e| error!("Can't read the forecast cache. {:?}", e))
+            .ok()?
+    }
+
+    fn store(&self, key: &Key, forecast: &Forecast) {
+        let fetched_at = self.clock.now().naive_utc();
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(*key, (fetched_at, forecast.clone()));
+        if let Some(ref pool) = self.pool {
+            let stored = pool
+                .get()
+                .map_err(|e| failure::Error::from(e))
+                .and_then(|conn| {
+                    CachedForecast {
+                        lat_key: key.0 as i32,
+                        lon_key: key.1 as i32,
+                        lang: key.2.code().to_owned(),
+                        data: serde_json::to_string(forecast)?,
+                        fetched_at,
+                    }
+                    .upsert(&conn)
+                    .map_err(|e| failure::Error::from(e))
+                });
+            if let Err(e) = stored {
+                error!("Can't store the forecast in the cache. {:?}", e);
+            }
+        }
+    }
+
+    fn fetch(&self, key: Key, lat: f64, lon: f64, lang: Lang) -> Result<Forecast, failure::Error> {
+        let (slot, is_leader) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(slot) => (slot.clone(), false),
+                None => {
+                    let slot = Arc::new(InFlight::default());
+                    in_flight.insert(key, slot.clone());
+                    (slot, true)
+                }
+            }
+        };
+        if !is_leader {
+            let mut result = slot.result.lock().unwrap();
+            while result.is_none() {
+                result = slot.done.wait(result).unwrap();
+            }
+            return match result.as_ref().unwrap() {
+                Ok(forecast) => Ok(forecast.clone()),
+                Err(msg) => Err(failure::Error::from(ProviderError { msg: msg.clone() })),
+            };
+        }
+        let fetched = self.inner.forecast(lat, lon, lang);
+        if let Ok(ref forecast) = fetched {
+            self.store(&key, forecast);
+        }
+        *slot.result.lock().unwrap() = Some(match fetched {
+            Ok(ref forecast) => Ok(forecast.clone()),
+            Err(ref e) => Err(e.to_string()),
+        });
+        slot.done.notify_all();
+        self.in_flight.lock().unwrap().remove(&key);
+        fetched
+    }
+}
+
+impl WeatherProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn forecast(&self, lat: f64, lon: f64, lang: Lang) -> Result<Forecast, failure::Error> {
+        let key_lang = if self.inner.is_localized() {
+            lang
+        } else {
+            Lang::default()
+        };
+        let key = (location_key(lat), location_key(lon), key_lang);
+        if let Some(forecast) = self.cached(&key) {
+            return Ok(forecast);
+        }
+        self.fetch(key, lat, lon, lang)
+            .or_else(|e| match self.stale(&key) {
+                Some(forecast) => {
+                    warn!("Serving an outdated forecast. {}", e);
+                    Ok(forecast)
+                }
+                None => Err(e),
+            })
+    }
+
+    fn has_alerts(&self) -> bool {
+        self.inner.has_alerts()
+    }
+
+    fn is_localized(&self) -> bool {
+        self.inner.is_localized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::FixedClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use std::time::Instant;
+    use testing::{fixture, recorded};
+
+    // counts the forecasts asked for
+    struct Counting(Arc<AtomicUsize>, bool);
+
+    impl WeatherProvider for Counting {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn forecast(&self, _lat: f64, _lon: f64, _lang: Lang) -> Result<Forecast, failure::Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(fixture())
+        }
+
+        fn is_localized(&self) -> bool {
+            self.1
+        }
+    }
+
+    fn provider(localized: bool) -> (CachedProvider, Arc<AtomicUsize>, Arc<FixedClock>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let clock = Arc::new(FixedClock::new(recorded()));
+        let cache = CachedProvider::new(
+            Box::new(Counting(calls.clone(), localized)),
+            Duration::minutes(30),
+            None,
+            clock.clone(),
+        );
+        (cache, calls, clock)
+    }
+
+    #[test]
+    fn fresh_for_the_ttl_of_the_clock() {
+        let (cache, calls, clock) = provider(false);
+        cache.forecast(50.45, 30.52, Lang::En).unwrap();
+        clock.advance(Duration::minutes(30) - Duration::seconds(1));
+        cache.forecast(50.45, 30.52, Lang::En).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        clock.advance(Duration::seconds(1));
+        cache.forecast(50.45, 30.52, Lang::En).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        // another place is fetched on its own
+        cache.forecast(49.84, 24.03, Lang::En).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn keyed_by_language_when_it_matters() {
+        let (cache, calls, _) = provider(false);
+        for lang in Lang::all() {
+            cache.forecast(50.45, 30.52, *lang).unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let (cache, calls, _) = provider(true);
+        for lang in Lang::all() {
+            cache.forecast(50.45, 30.52, *lang).unwrap();
+            cache.forecast(50.45, 30.52, *lang).unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), Lang::all().len());
+    }
+
+    // tells when a fetch starts, then holds it until released
+    struct Gated {
+        calls: Arc<AtomicUsize>,
+        started: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl WeatherProvider for Gated {
+        fn name(&self) -> &'static str {
+            "gated"
+        }
+
+        fn forecast(&self, _lat: f64, _lon: f64, _lang: Lang) -> Result<Forecast, failure::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            Ok(fixture())
+        }
+    }
+
+    #[test]
+    fn concurrent_requests_share_one_fetch() {
+        let (started_tx, started) = channel();
+        let (release, release_rx) = channel();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let gated = Gated {
+            calls: calls.clone(),
+            started: Mutex::new(started_tx),
+            release: Mutex::new(release_rx),
+        };
+        let cache = Arc::new(CachedProvider::new(
+            Box::new(gated),
+            Duration::minutes(30),
+            None,
+            Arc::new(FixedClock::new(recorded())),
+        ));
+        let request = |cache: &Arc<CachedProvider>| {
+            let cache = cache.clone();
+            thread::spawn(move || cache.forecast(50.45, 30.52, Lang::En).map(|f| f.timezone))
+        };
+        let first = request(&cache);
+        started.recv().unwrap();
+        let second = request(&cache);
+        // the second request waits for the first one's fetch, holding the slot with it
+        let key = (location_key(50.45), location_key(30.52), Lang::default());
+        let deadline = Instant::now() + ::std::time::Duration::from_secs(10);
+        while cache
+            .in_flight
+            .lock()
+            .unwrap()
+            .get(&key)
+            .map(Arc::strong_count)
+            != Some(3)
+        {
+            assert!(Instant::now() < deadline, "the second request didn't wait");
+            thread::yield_now();
+        }
+        release.send(()).unwrap();
+        assert_eq!(first.join().unwrap().unwrap(), "Europe/Kiev");
+        assert_eq!(second.join().unwrap().unwrap(), "Europe/Kiev");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(cache.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+use i18n::Lang;
+use serde_json;
+use std::fs::File;
+use std::path::PathBuf;
+use weather::*;
+
+static DEFAULT_FIXTURE: &str = "default.json";
+
+// files are named after the coordinates, e.g. "50.45_30.52.json", "default.json" for the others
+pub struct FixtureProvider {
+    dir: PathBuf,
+}
+
+impl FixtureProvider {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FixtureProvider {
+        FixtureProvider { dir: dir.into() }
+    }
+
+    fn path(&self, lat: f64, lon: f64) -> PathBuf {
+        let path = self.dir.join(format!("{:.2}_{:.2}.json", lat, lon));
+        if path.exists() {
+            path
+        } else {
+            self.dir.join(DEFAULT_FIXTURE)
+        }
+    }
+}
+
+impl WeatherProvider for FixtureProvider {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn forecast(&self, lat: f64, lon: f64, _lang: Lang) -> Result<Forecast, failure::Error> {
+        let path = self.path(lat, lon);
+        let file = File::open(&path).map_err(|e| ProviderError {
+            msg: format!("Can't open fixture {}: {}", path.display(), e),
+        })?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn has_alerts(&self) -> bool {
+        true
+    }
+}
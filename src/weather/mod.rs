@@ -0,0 +1,408 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz;
+use chrono_tz::Tz;
+use clock::Clock;
+use config::{Config, ConfigError};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use i18n::Lang;
+use std::sync::Arc;
+use units::UnitSystem;
+use usage::UsageTracker;
+
+pub mod cache;
+pub mod fixture;
+pub mod open_meteo;
+pub mod open_weather_map;
+pub mod tracked;
+
+// the normalized model is in ℃, m/s, mm/h, km and hPa
+pub const UNITS: UnitSystem = UnitSystem::Si;
+static FORECAST_CACHE_TTL: i64 = 30;
+
+// about a kilometer, close enough to share a forecast
+pub fn location_key(value: f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Weather provider error: {}", msg)]
+pub struct ProviderError {
+    pub msg: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrecipType {
+    Rain,
+    Snow,
+    Sleet,
+}
+
+// named after the icon codes of the Dark Sky API
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Icon {
+    ClearDay,
+    ClearNight,
+    Rain,
+    Snow,
+    Sleet,
+    Wind,
+    Fog,
+    Cloudy,
+    PartlyCloudyDay,
+    PartlyCloudyNight,
+    Hail,
+    Thunderstorm,
+    Tornado,
+}
+
+impl Icon {
+    pub fn all() -> &'static [Icon] {
+        &[
+            Icon::ClearDay,
+            Icon::ClearNight,
+            Icon::Rain,
+            Icon::Snow,
+            Icon::Sleet,
+            Icon::Wind,
+            Icon::Fog,
+            Icon::Cloudy,
+            Icon::PartlyCloudyDay,
+            Icon::PartlyCloudyNight,
+            Icon::Hail,
+            Icon::Thunderstorm,
+            Icon::Tornado,
+        ]
+    }
+
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Icon::ClearDay => "clear-day",
+            Icon::ClearNight => "clear-night",
+            Icon::Rain => "rain",
+            Icon::Snow => "snow",
+            Icon::Sleet => "sleet",
+            Icon::Wind => "wind",
+            Icon::Fog => "fog",
+            Icon::Cloudy => "cloudy",
+            Icon::PartlyCloudyDay => "partly-cloudy-day",
+            Icon::PartlyCloudyNight => "partly-cloudy-night",
+            Icon::Hail => "hail",
+            Icon::Thunderstorm => "thunderstorm",
+            Icon::Tornado => "tornado",
+        }
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        match *self {
+            Icon::ClearDay => "☀️",
+            Icon::ClearNight => "🌙",
+            Icon::Rain => "🌧",
+            Icon::Snow => "❄️",
+            Icon::Sleet => "🌨",
+            Icon::Wind => "💨",
+            Icon::Fog => "🌫",
+            Icon::Cloudy => "☁️",
+            Icon::PartlyCloudyDay => "⛅",
+            Icon::PartlyCloudyNight => "☁️🌙",
+            Icon::Hail => "🧊",
+            Icon::Thunderstorm => "⛈",
+            Icon::Tornado => "🌪",
+        }
+    }
+
+    pub fn image(&self) -> String {
+        format!("icons/{}.png", self.code())
+    }
+}
+
+// ratios (humidity, cloud cover, probabilities) are 0..1
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DataPoint {
+    // local midnight for the daily points
+    pub time: u64,
+    pub summary: Option<String>,
+    pub icon: Option<Icon>,
+    pub temperature: Option<f64>,
+    pub apparent_temperature: Option<f64>,
+    pub temperature_low: Option<f64>,
+    pub temperature_high: Option<f64>,
+    pub apparent_temperature_low: Option<f64>,
+    pub apparent_temperature_high: Option<f64>,
+    pub dew_point: Option<f64>,
+    pub humidity: Option<f64>,
+    pub pressure: Option<f64>,
+    pub wind_speed: Option<f64>,
+    pub wind_gust: Option<f64>,
+    pub wind_bearing: Option<f64>,
+    pub cloud_cover: Option<f64>,
+    pub uv_index: Option<f64>,
+    pub visibility: Option<f64>,
+    pub precip_intensity: Option<f64>,
+    pub precip_probability: Option<f64>,
+    pub precip_type: Option<PrecipType>,
+    pub sunrise_time: Option<u64>,
+    pub sunset_time: Option<u64>,
+    pub moon_phase: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    pub id: String,
+    pub title: String,
+    // a key of the localized title, `alert_<kind>` in the catalog
+    #[serde(default)]
+    pub kind: Option<String>,
+    pub severity: Option<String>,
+    pub time: u64,
+    pub expires: Option<u64>,
+    pub description: Option<String>,
+}
+
+static DEFAULT_ALERT_DURATION: u64 = 24 * 60 * 60;
+
+impl Alert {
+    pub fn expires(&self) -> u64 {
+        self.expires.unwrap_or(self.time + DEFAULT_ALERT_DURATION)
+    }
+
+    pub fn expires_at(&self) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(self.expires() as i64, 0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Forecast {
+    pub timezone: String,
+    pub current: Option<DataPoint>,
+    pub minutely: Vec<DataPoint>,
+    pub hourly: Vec<DataPoint>,
+    pub daily: Vec<DataPoint>,
+    pub alerts: Vec<Alert>,
+}
+
+impl Forecast {
+    pub fn tz(&self) -> Tz {
+        match self.timezone.as_str() {
+            // renamed in the 2022 tz database
+            "Europe/Kyiv" => chrono_tz::Europe::Kiev,
+            name => name.parse().unwrap_or_else(|_| {
+                warn!("Unknown timezone of a forecast: {}", name);
+                chrono_tz::Europe::Kiev
+            }),
+        }
+    }
+
+    pub fn date_of(&self, time: u64) -> NaiveDate {
+        self.tz().timestamp(time as i64, 0).naive_local().date()
+    }
+
+    pub fn date_at(&self, now: DateTime<Utc>) -> NaiveDate {
+        now.with_timezone(&self.tz()).naive_local().date()
+    }
+
+    pub fn day(&self, date: NaiveDate) -> Option<&DataPoint> {
+        self.daily.iter().find(|dp| self.date_of(dp.time) == date)
+    }
+
+    pub fn tomorrow(&self, now: DateTime<Utc>) -> Option<&DataPoint> {
+        self.day(self.date_at(now).succ())
+    }
+
+    // the daily forecast should start with today at the location
+    pub fn is_outdated(&self, now: DateTime<Utc>) -> bool {
+        self.daily
+            .first()
+            .map_or(true, |first| self.date_of(first.time) != self.date_at(now))
+    }
+}
+
+pub trait WeatherProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn forecast(&self, lat: f64, lon: f64, lang: Lang) -> Result<Forecast, failure::Error>;
+
+    fn has_alerts(&self) -> bool {
+        false
+    }
+
+    // whether the texts of the forecast come in the language asked for
+    fn is_localized(&self) -> bool {
+        false
+    }
+}
+
+pub fn from_config(config: &Config) -> Result<Box<WeatherProvider>, ConfigError> {
+    let provider: Box<WeatherProvider> = match config.weather_provider.as_ref().map(|p| p.as_str())
+    {
+        Some("openweathermap") => Box::new(open_weather_map::OpenWeatherMap::new(
+            config.open_weather_map_api_key.clone().ok_or(ConfigError {
+                msg: "no OpenWeatherMap api key".to_owned(),
+            })?,
+        )),
+        Some("fixture") => Box::new(fixture::FixtureProvider::new(
+            config
+                .fixtures_dir
+                .clone()
+                .unwrap_or_else(|| "fixtures".to_owned()),
+        )),
+        Some("open-meteo") | None => Box::new(open_meteo::OpenMeteo::new()),
+        Some(other) => {
+            return Err(ConfigError {
+                msg: format!("unknown weather provider: {}", other),
+            })
+        }
+    };
+    if !provider.has_alerts() {
+        warn!("The weather provider has no severe weather alerts, they won't be forwarded.");
+    }
+    Ok(provider)
+}
+
+// the cache goes first, so that only the requests it can't serve count against the budget
+pub fn cached_from_config(
+    config: &Config,
+    pool: &Pool<ConnectionManager<PgConnection>>,
+    usage: Arc<UsageTracker>,
+    clock: Arc<Clock>,
+) -> Result<Box<WeatherProvider>, ConfigError> {
+    let ttl = Duration::minutes(config.forecast_cache_ttl.unwrap_or(FORECAST_CACHE_TTL));
+    let pool = if config.forecast_cache_db.unwrap_or(false) {
+        Some(pool.clone())
+    } else {
+        None
+    };
+    let tracked = tracked::TrackedProvider::new(from_config(config)?, usage);
+    Ok(Box::new(cache::CachedProvider::new(
+        Box::new(tracked),
+        ttl,
+        pool,
+        clock,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Kiev;
+
+    // daily points at the local midnights of the days of the month
+    fn forecast(year: i32, month: u32, days: &[u32]) -> Forecast {
+        Forecast {
+            timezone: "Europe/Kiev".to_owned(),
+            daily: days
+                .iter()
+                .map(|day| DataPoint {
+                    time: Kiev.ymd(year, month, *day).and_hms(0, 0, 0).timestamp() as u64,
+                    ..DataPoint::default()
+                })
+                .collect(),
+            ..Forecast::default()
+        }
+    }
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2026, month, day).and_hms(hour, minute, 0)
+    }
+
+    // Kyiv moves from UTC+2 to UTC+3 at 01:00 UTC on March 29, 2026
+    #[test]
+    fn spring_forward() {
+        let forecast = forecast(2026, 3, &[28, 29, 30, 31]);
+        assert_eq!(
+            forecast.date_of(forecast.daily[2].time),
+            NaiveDate::from_ymd(2026, 3, 30)
+        );
+        // 00:30 on March 29, still in winter time
+        let day = forecast.tomorrow(utc(3, 28, 22, 30)).unwrap();
+        assert_eq!(day.time, forecast.daily[2].time);
+        // 23:30 on March 29, a 23 hour day
+        let day = forecast.tomorrow(utc(3, 29, 20, 30)).unwrap();
+        assert_eq!(day.time, forecast.daily[2].time);
+        // 00:30 on March 30, while UTC+2 would still be on March 29
+        let day = forecast.tomorrow(utc(3, 29, 21, 30)).unwrap();
+        assert_eq!(day.time, forecast.daily[3].time);
+    }
+
+    // Kyiv moves back from UTC+3 to UTC+2 at 01:00 UTC on October 25, 2026
+    #[test]
+    fn fall_back() {
+        let forecast = forecast(2026, 10, &[24, 25, 26, 27]);
+        assert_eq!(
+            forecast.date_of(forecast.daily[2].time),
+            NaiveDate::from_ymd(2026, 10, 26)
+        );
+        // 03:30 happens twice on October 25
+        let before = utc(10, 25, 0, 30);
+        let after = utc(10, 25, 1, 30);
+        assert_eq!(forecast.date_at(before), forecast.date_at(after));
+        assert_eq!(
+            forecast.tomorrow(after).unwrap().time,
+            forecast.daily[2].time
+        );
+        // 23:30 on October 25, a 25 hour day
+        let day = forecast.tomorrow(utc(10, 25, 21, 30)).unwrap();
+        assert_eq!(day.time, forecast.daily[2].time);
+        // 00:30 on October 26
+        let day = forecast.tomorrow(utc(10, 25, 22, 30)).unwrap();
+        assert_eq!(day.time, forecast.daily[3].time);
+    }
+
+    #[test]
+    fn outdated_after_the_local_midnight() {
+        let spring = forecast(2026, 3, &[29, 30]);
+        assert!(!spring.is_outdated(utc(3, 29, 20, 59)));
+        assert!(spring.is_outdated(utc(3, 29, 21, 0)));
+        let fall = forecast(2026, 10, &[25, 26]);
+        assert!(!fall.is_outdated(utc(10, 25, 21, 59)));
+        assert!(fall.is_outdated(utc(10, 25, 22, 0)));
+        assert!(Forecast::default().is_outdated(utc(10, 25, 22, 0)));
+    }
+
+    #[test]
+    fn every_icon_has_an_emoji_and_a_picture() {
+        let catalog = ::testing::catalog();
+        for icon in Icon::all() {
+            assert!(!icon.emoji().is_empty(), "no emoji for {}", icon.code());
+            let path = ::std::path::Path::new("static").join(icon.image());
+            assert!(path.is_file(), "no picture for {}", icon.code());
+            let key = format!("icon_{}", icon.code());
+            for lang in Lang::all() {
+                assert_ne!(catalog.get(*lang, &key), key, "no name for {}", icon.code());
+            }
+            // providers and fixtures refer to the icons by their codes
+            let parsed: Icon = ::serde_json::from_str(&format!("\"{}\"", icon.code())).unwrap();
+            assert_eq!(parsed, *icon);
+        }
+    }
+
+    #[test]
+    fn all_lists_every_icon() {
+        // a new icon doesn't compile here until it gets a place in `Icon::all`
+        let place = |icon: &Icon| match icon {
+            Icon::ClearDay => 0,
+            Icon::ClearNight => 1,
+            Icon::Rain => 2,
+            Icon::Snow => 3,
+            Icon::Sleet => 4,
+            Icon::Wind => 5,
+            Icon::Fog => 6,
+            Icon::Cloudy => 7,
+            Icon::PartlyCloudyDay => 8,
+            Icon::PartlyCloudyNight => 9,
+            Icon::Hail => 10,
+            Icon::Thunderstorm => 11,
+            Icon::Tornado => 12,
+        };
+        let places: Vec<usize> = Icon::all().iter().map(place).collect();
+        assert_eq!(places, (0..13).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn no_tomorrow_past_the_forecast() {
+        let forecast = forecast(2026, 3, &[29, 30]);
+        assert!(forecast.tomorrow(utc(3, 30, 12, 0)).is_none());
+    }
+}
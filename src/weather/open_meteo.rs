@@ -0,0 +1,304 @@
+use i18n::Lang;
+use weather::*;
+
+static API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+static FORECAST_DAYS: &str = "8";
+static CURRENT: &str = "temperature_2m,apparent_temperature,relative_humidity_2m,precipitation,\
+weather_code,cloud_cover,pressure_msl,wind_speed_10m,wind_direction_10m,wind_gusts_10m,is_day";
+static MINUTELY: &str = "precipitation";
+static HOURLY: &str = "temperature_2m,apparent_temperature,relative_humidity_2m,dew_point_2m,\
+precipitation_probability,precipitation,weather_code,pressure_msl,cloud_cover,visibility,\
+wind_speed_10m,wind_direction_10m,wind_gusts_10m,uv_index,is_day";
+static DAILY: &str = "weather_code,temperature_2m_max,temperature_2m_min,apparent_temperature_max,\
+apparent_temperature_min,sunrise,sunset,uv_index_max,precipitation_sum,precipitation_probability_max,\
+wind_speed_10m_max,wind_gusts_10m_max,wind_direction_10m_dominant";
+// minutely data comes in 15 minute steps
+static STEPS_IN_HOUR: f64 = 4.0;
+
+// https://open-meteo.com/en/docs, no texts and no alerts
+pub struct OpenMeteo {
+    client: reqwest::Client,
+}
+
+impl OpenMeteo {
+    pub fn new() -> OpenMeteo {
+        OpenMeteo {
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Current {
+    time: u64,
+    temperature_2m: Option<f64>,
+    apparent_temperature: Option<f64>,
+    relative_humidity_2m: Option<f64>,
+    precipitation: Option<f64>,
+    weather_code: Option<u8>,
+    cloud_cover: Option<f64>,
+    pressure_msl: Option<f64>,
+    wind_speed_10m: Option<f64>,
+    wind_direction_10m: Option<f64>,
+    wind_gusts_10m: Option<f64>,
+    is_day: Option<u8>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Minutely {
+    time: Vec<u64>,
+    precipitation: Vec<Option<f64>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Hourly {
+    time: Vec<u64>,
+    temperature_2m: Vec<Option<f64>>,
+    apparent_temperature: Vec<Option<f64>>,
+    relative_humidity_2m: Vec<Option<f64>>,
+    dew_point_2m: Vec<Option<f64>>,
+    precipitation_probability: Vec<Option<f64>>,
+    precipitation: Vec<Option<f64>>,
+    weather_code: Vec<Option<u8>>,
+    pressure_msl: Vec<Option<f64>>,
+    cloud_cover: Vec<Option<f64>>,
+    visibility: Vec<Option<f64>>,
+    wind_speed_10m: Vec<Option<f64>>,
+    wind_direction_10m: Vec<Option<f64>>,
+    wind_gusts_10m: Vec<Option<f64>>,
+    uv_index: Vec<Option<f64>>,
+    is_day: Vec<Option<u8>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Daily {
+    time: Vec<u64>,
+    weather_code: Vec<Option<u8>>,
+    temperature_2m_max: Vec<Option<f64>>,
+    temperature_2m_min: Vec<Option<f64>>,
+    apparent_temperature_max: Vec<Option<f64>>,
+    apparent_temperature_min: Vec<Option<f64>>,
+    sunrise: Vec<Option<u64>>,
+    sunset: Vec<Option<u64>>,
+    uv_index_max: Vec<Option<f64>>,
+    precipitation_sum: Vec<Option<f64>>,
+    precipitation_probability_max: Vec<Option<f64>>,
+    wind_speed_10m_max: Vec<Option<f64>>,
+    wind_gusts_10m_max: Vec<Option<f64>>,
+    wind_direction_10m_dominant: Vec<Option<f64>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    timezone: String,
+    current: Option<Current>,
+    #[serde(default)]
+    minutely_15: Minutely,
+    #[serde(default)]
+    hourly: Hourly,
+    #[serde(default)]
+    daily: Daily,
+}
+
+// the API leaves out the series it has no data for
+fn at<T: Copy>(series: &[Option<T>], i: usize) -> Option<T> {
+    series.get(i).cloned().unwrap_or(None)
+}
+
+fn ratio(percent: Option<f64>) -> Option<f64> {
+    percent.map(|p| p / 100.0)
+}
+
+fn condition(code: Option<u8>, is_day: bool) -> (Option<Icon>, Option<PrecipType>) {
+    let code = match code {
+        Some(code) => code,
+        None => return (None, None),
+    };
+    match code {
+        0 if is_day => (Some(Icon::ClearDay), None),
+        0 => (Some(Icon::ClearNight), None),
+        1 | 2 if is_day => (Some(Icon::PartlyCloudyDay), None),
+        1 | 2 => (Some(Icon::PartlyCloudyNight), None),
+        3 => (Some(Icon::Cloudy), None),
+        45 | 48 => (Some(Icon::Fog), None),
+        56 | 57 | 66 | 67 => (Some(Icon::Sleet), Some(PrecipType::Sleet)),
+        51..=65 | 80..=82 => (Some(Icon::Rain), Some(PrecipType::Rain)),
+        71..=77 | 85 | 86 => (Some(Icon::Snow), Some(PrecipType::Snow)),
+        95 => (Some(Icon::Thunderstorm), Some(PrecipType::Rain)),
+        96 | 99 => (Some(Icon::Hail), Some(PrecipType::Rain)),
+        _ => (None, None),
+    }
+}
+
+impl Response {
+    fn current(&self) -> Option<DataPoint> {
+        let c = self.current.as_ref()?;
+        let (icon, precip_type) = condition(c.weather_code, c.is_day != Some(0));
+        Some(DataPoint {
+            time: c.time,
+            icon,
+            temperature: c.temperature_2m,
+            apparent_temperature: c.apparent_temperature,
+            humidity: ratio(c.relative_humidity_2m),
+            pressure: c.pressure_msl,
+            wind_speed: c.wind_speed_10m,
+            wind_gust: c.wind_gusts_10m,
+            wind_bearing: c.wind_direction_10m,
+            cloud_cover: ratio(c.cloud_cover),
+            precip_intensity: c.precipitation,
+            precip_type,
+            ..DataPoint::default()
+        })
+    }
+
+    fn minutely(&self) -> Vec<DataPoint> {
+        let m = &self.minutely_15;
+        m.time
+            .iter()
+            .enumerate()
+            .map(|(i, &time)| DataPoint {
+                time,
+                precip_intensity: at(&m.precipitation, i).map(|p| p * STEPS_IN_HOUR),
+                ..DataPoint::default()
+            })
+            .collect()
+    }
+
+    fn hourly(&self) -> Vec<DataPoint> {
+        let h = &self.hourly;
+        h.time
+            .iter()
+            .enumerate()
+            .map(|(i, &time)| {
+                let (icon, precip_type) =
+                    condition(at(&h.weather_code, i), at(&h.is_day, i) != Some(0));
+                DataPoint {
+                    time,
+                    icon,
+                    temperature: at(&h.temperature_2m, i),
+                    apparent_temperature: at(&h.apparent_temperature, i),
+                    dew_point: at(&h.dew_point_2m, i),
+                    humidity: ratio(at(&h.relative_humidity_2m, i)),
+                    pressure: at(&h.pressure_msl, i),
+                    wind_speed: at(&h.wind_speed_10m, i),
+                    wind_gust: at(&h.wind_gusts_10m, i),
+                    wind_bearing: at(&h.wind_direction_10m, i),
+                    cloud_cover: ratio(at(&h.cloud_cover, i)),
+                    uv_index: at(&h.uv_index, i),
+                    // meters
+                    visibility: at(&h.visibility, i).map(|v| v / 1000.0),
+                    precip_intensity: at(&h.precipitation, i),
+                    precip_probability: ratio(at(&h.precipitation_probability, i)),
+                    precip_type,
+                    ..DataPoint::default()
+                }
+            })
+            .collect()
+    }
+
+    fn daily(&self) -> Vec<DataPoint> {
+        let d = &self.daily;
+        d.time
+            .iter()
+            .enumerate()
+            .map(|(i, &time)| {
+                let (icon, precip_type) = condition(at(&d.weather_code, i), true);
+                DataPoint {
+                    time,
+                    icon,
+                    temperature_low: at(&d.temperature_2m_min, i),
+                    temperature_high: at(&d.temperature_2m_max, i),
+                    apparent_temperature_low: at(&d.apparent_temperature_min, i),
+                    apparent_temperature_high: at(&d.apparent_temperature_max, i),
+                    wind_speed: at(&d.wind_speed_10m_max, i),
+                    wind_gust: at(&d.wind_gusts_10m_max, i),
+                    wind_bearing: at(&d.wind_direction_10m_dominant, i),
+                    uv_index: at(&d.uv_index_max, i),
+                    // the daily sum, averaged over the day
+                    precip_intensity: at(&d.precipitation_sum, i).map(|p| p / 24.0),
+                    precip_probability: ratio(at(&d.precipitation_probability_max, i)),
+                    precip_type,
+                    sunrise_time: at(&d.sunrise, i),
+                    sunset_time: at(&d.sunset, i),
+                    ..DataPoint::default()
+                }
+            })
+            .collect()
+    }
+
+    fn normalize(&self) -> Forecast {
+        Forecast {
+            timezone: self.timezone.clone(),
+            current: self.current(),
+            minutely: self.minutely(),
+            hourly: self.hourly(),
+            daily: self.daily(),
+            alerts: Vec::new(),
+        }
+    }
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "open-meteo"
+    }
+
+    fn forecast(&self, lat: f64, lon: f64, _lang: Lang) -> Result<Forecast, failure::Error> {
+        let mut response = self
+            .client
+            .get(API_URL)
+            .query(&[
+                ("latitude", lat.to_string().as_str()),
+                ("longitude", lon.to_string().as_str()),
+                ("current", CURRENT),
+                ("minutely_15", MINUTELY),
+                ("hourly", HOURLY),
+                ("daily", DAILY),
+                ("timezone", "auto"),
+                ("timeformat", "unixtime"),
+                ("wind_speed_unit", "ms"),
+                ("forecast_days", FORECAST_DAYS),
+            ])
+            .send()?;
+        if !response.status().is_success() {
+            return Err(failure::Error::from(ProviderError {
+                msg: format!("Open-Meteo response failure: {}", response.text()?),
+            }));
+        }
+        let resp: Response = response.json()?;
+        Ok(resp.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the weather codes Open-Meteo returns, see "WMO Weather interpretation codes" in its docs
+    static WMO_CODES: &[u8] = &[
+        0, 1, 2, 3, 45, 48, 51, 53, 55, 56, 57, 61, 63, 65, 66, 67, 71, 73, 75, 77, 80, 81, 82, 85,
+        86, 95, 96, 99,
+    ];
+
+    #[test]
+    fn every_code_has_an_icon() {
+        for code in WMO_CODES {
+            for is_day in &[true, false] {
+                assert!(
+                    condition(Some(*code), *is_day).0.is_some(),
+                    "no icon for {}",
+                    code
+                );
+            }
+        }
+        assert_eq!(condition(Some(0), false).0, Some(Icon::ClearNight));
+        assert_eq!(
+            condition(Some(57), true),
+            (Some(Icon::Sleet), Some(PrecipType::Sleet))
+        );
+        assert_eq!(condition(None, true), (None, None));
+    }
+}
@@ -0,0 +1,410 @@
+use i18n::Lang;
+use std::collections::HashMap;
+use weather::*;
+
+static API_URL: &str = "https://api.openweathermap.org/data/3.0/onecall";
+static SECONDS_IN_DAY: i64 = 24 * 60 * 60;
+
+// https://openweathermap.org/api/one-call-3
+pub struct OpenWeatherMap {
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl OpenWeatherMap {
+    pub fn new(api_key: String) -> OpenWeatherMap {
+        OpenWeatherMap {
+            api_key,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Weather {
+    id: u16,
+    description: String,
+    icon: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Point {
+    dt: u64,
+    sunrise: Option<u64>,
+    sunset: Option<u64>,
+    temp: Option<f64>,
+    feels_like: Option<f64>,
+    pressure: Option<f64>,
+    humidity: Option<f64>,
+    dew_point: Option<f64>,
+    uvi: Option<f64>,
+    clouds: Option<f64>,
+    visibility: Option<f64>,
+    wind_speed: Option<f64>,
+    wind_gust: Option<f64>,
+    wind_deg: Option<f64>,
+    pop: Option<f64>,
+    #[serde(default)]
+    weather: Vec<Weather>,
+    rain: Option<HashMap<String, f64>>,
+    snow: Option<HashMap<String, f64>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Minute {
+    dt: u64,
+    precipitation: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DailyTemperature {
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DailyFeelsLike {
+    day: Option<f64>,
+    night: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Day {
+    dt: u64,
+    sunrise: Option<u64>,
+    sunset: Option<u64>,
+    moon_phase: Option<f64>,
+    summary: Option<String>,
+    temp: Option<DailyTemperature>,
+    feels_like: Option<DailyFeelsLike>,
+    pressure: Option<f64>,
+    humidity: Option<f64>,
+    dew_point: Option<f64>,
+    wind_speed: Option<f64>,
+    wind_gust: Option<f64>,
+    wind_deg: Option<f64>,
+    clouds: Option<f64>,
+    uvi: Option<f64>,
+    pop: Option<f64>,
+    rain: Option<f64>,
+    snow: Option<f64>,
+    #[serde(default)]
+    weather: Vec<Weather>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OwmAlert {
+    sender_name: String,
+    event: String,
+    start: u64,
+    end: u64,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// the alert tags OpenWeatherMap returns, with the kind and the severity of each,
+// the most severe first. The event itself is free text from a national weather service
+static ALERT_TAGS: &[(&str, &str, &str)] = &[
+    ("Extreme temperature value", "temperature", "warning"),
+    ("Flood", "flood", "warning"),
+    ("Thunderstorm", "thunderstorm", "warning"),
+    ("Fire warning", "fire", "warning"),
+    ("Avalanches", "avalanches", "warning"),
+    ("Tsunami", "tsunami", "warning"),
+    ("Wind", "wind", "watch"),
+    ("Snow/Ice", "snow_ice", "watch"),
+    ("Rain", "rain", "watch"),
+    ("Coastal event", "coastal", "watch"),
+    ("Marine event", "marine", "watch"),
+    ("Fog", "fog", "advisory"),
+    ("Air quality", "air_quality", "advisory"),
+    ("Other dangers", "other", "advisory"),
+];
+
+impl OwmAlert {
+    fn normalize(&self) -> Alert {
+        let tag = ALERT_TAGS
+            .iter()
+            .find(|(name, _, _)| self.tags.iter().any(|t| t.eq_ignore_ascii_case(name)));
+        Alert {
+            id: format!("{}/{}#{}", self.sender_name, self.event, self.start),
+            title: self.event.clone(),
+            kind: tag.map(|(_, kind, _)| kind.to_string()),
+            severity: tag.map(|(_, _, severity)| severity.to_string()),
+            time: self.start,
+            expires: Some(self.end),
+            description: self.description.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    timezone: String,
+    #[serde(default)]
+    timezone_offset: i64,
+    current: Option<Point>,
+    #[serde(default)]
+    minutely: Vec<Minute>,
+    #[serde(default)]
+    hourly: Vec<Point>,
+    #[serde(default)]
+    daily: Vec<Day>,
+    #[serde(default)]
+    alerts: Vec<OwmAlert>,
+}
+
+fn ratio(percent: Option<f64>) -> Option<f64> {
+    percent.map(|p| p / 100.0)
+}
+
+// https://openweathermap.org/weather-conditions
+fn condition(weather: &[Weather]) -> (Option<Icon>, Option<PrecipType>) {
+    let weather = match weather.first() {
+        Some(weather) => weather,
+        None => return (None, None),
+    };
+    let is_day = !weather.icon.ends_with('n');
+    match weather.id {
+        200..=232 => (Some(Icon::Thunderstorm), Some(PrecipType::Rain)),
+        511 | 611..=616 => (Some(Icon::Sleet), Some(PrecipType::Sleet)),
+        300..=321 | 500..=531 => (Some(Icon::Rain), Some(PrecipType::Rain)),
+        600..=622 => (Some(Icon::Snow), Some(PrecipType::Snow)),
+        771 => (Some(Icon::Wind), None),
+        781 => (Some(Icon::Tornado), None),
+        701..=762 => (Some(Icon::Fog), None),
+        800 if is_day => (Some(Icon::ClearDay), None),
+        800 => (Some(Icon::ClearNight), None),
+        801 | 802 if is_day => (Some(Icon::PartlyCloudyDay), None),
+        801 | 802 => (Some(Icon::PartlyCloudyNight), None),
+        803 | 804 => (Some(Icon::Cloudy), None),
+        _ => (None, None),
+    }
+}
+
+fn summary(weather: &[Weather]) -> Option<String> {
+    weather.first().map(|w| w.description.clone())
+}
+
+fn last_hour(volume: &Option<HashMap<String, f64>>) -> Option<f64> {
+    volume.as_ref().and_then(|v| v.get("1h").cloned())
+}
+
+impl Point {
+    fn normalize(&self) -> DataPoint {
+        let (icon, precip_type) = condition(&self.weather);
+        let precip = match (last_hour(&self.rain), last_hour(&self.snow)) {
+            (None, None) => None,
+            (rain, snow) => Some(rain.unwrap_or_default() + snow.unwrap_or_default()),
+        };
+        DataPoint {
+            time: self.dt,
+            summary: summary(&self.weather),
+            icon,
+            temperature: self.temp,
+            apparent_temperature: self.feels_like,
+            dew_point: self.dew_point,
+            humidity: ratio(self.humidity),
+            pressure: self.pressure,
+            wind_speed: self.wind_speed,
+            wind_gust: self.wind_gust,
+            wind_bearing: self.wind_deg,
+            cloud_cover: ratio(self.clouds),
+            uv_index: self.uvi,
+            // meters
+            visibility: self.visibility.map(|v| v / 1000.0),
+            precip_intensity: precip,
+            precip_probability: self.pop,
+            precip_type,
+            sunrise_time: self.sunrise,
+            sunset_time: self.sunset,
+            ..DataPoint::default()
+        }
+    }
+}
+
+impl Day {
+    // `offset` is the UTC offset of the location in seconds
+    fn normalize(&self, offset: i64) -> DataPoint {
+        let (icon, precip_type) = condition(&self.weather);
+        let precip = match (self.rain, self.snow) {
+            (None, None) => None,
+            (rain, snow) => Some((rain.unwrap_or_default() + snow.unwrap_or_default()) / 24.0),
+        };
+        // daily points are at noon, the normalized ones at midnight
+        let local = self.dt as i64 + offset;
+        DataPoint {
+            time: (self.dt as i64 - local.rem_euclid(SECONDS_IN_DAY)) as u64,
+            summary: self.summary.clone().or_else(|| summary(&self.weather)),
+            icon,
+            temperature_low: self.temp.as_ref().and_then(|t| t.min),
+            temperature_high: self.temp.as_ref().and_then(|t| t.max),
+            apparent_temperature_low: self.feels_like.as_ref().and_then(|f| f.night),
+            apparent_temperature_high: self.feels_like.as_ref().and_then(|f| f.day),
+            dew_point: self.dew_point,
+            humidity: ratio(self.humidity),
+            pressure: self.pressure,
+            wind_speed: self.wind_speed,
+            wind_gust: self.wind_gust,
+            wind_bearing: self.wind_deg,
+            cloud_cover: ratio(self.clouds),
+            uv_index: self.uvi,
+            precip_intensity: precip,
+            precip_probability: self.pop,
+            precip_type,
+            sunrise_time: self.sunrise,
+            sunset_time: self.sunset,
+            moon_phase: self.moon_phase,
+            ..DataPoint::default()
+        }
+    }
+}
+
+impl Response {
+    fn normalize(&self) -> Forecast {
+        Forecast {
+            timezone: self.timezone.clone(),
+            current: self.current.as_ref().map(|c| c.normalize()),
+            minutely: self
+                .minutely
+                .iter()
+                .map(|m| DataPoint {
+                    time: m.dt,
+                    precip_intensity: m.precipitation,
+                    ..DataPoint::default()
+                })
+                .collect(),
+            hourly: self.hourly.iter().map(|h| h.normalize()).collect(),
+            daily: self
+                .daily
+                .iter()
+                .map(|d| d.normalize(self.timezone_offset))
+                .collect(),
+            alerts: self.alerts.iter().map(|a| a.normalize()).collect(),
+        }
+    }
+}
+
+fn provider_lang(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Uk => "ua",
+        Lang::En => "en",
+        Lang::Ru => "ru",
+    }
+}
+
+impl WeatherProvider for OpenWeatherMap {
+    fn name(&self) -> &'static str {
+        "openweathermap"
+    }
+
+    fn has_alerts(&self) -> bool {
+        true
+    }
+
+    fn is_localized(&self) -> bool {
+        true
+    }
+
+    fn forecast(&self, lat: f64, lon: f64, lang: Lang) -> Result<Forecast, failure::Error> {
+        let mut response = self
+            .client
+            .get(API_URL)
+            .query(&[
+                ("lat", lat.to_string().as_str()),
+                ("lon", lon.to_string().as_str()),
+                ("appid", self.api_key.as_str()),
+                ("units", "metric"),
+                ("lang", provider_lang(lang)),
+            ])
+            .send()?;
+        if !response.status().is_success() {
+            return Err(failure::Error::from(ProviderError {
+                msg: format!("OpenWeatherMap response failure: {}", response.text()?),
+            }));
+        }
+        let resp: Response = response.json()?;
+        Ok(resp.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the condition ids OpenWeatherMap returns, see https://openweathermap.org/weather-conditions
+    static CONDITION_IDS: &[u16] = &[
+        200, 201, 202, 210, 211, 212, 221, 230, 231, 232, 300, 301, 302, 310, 311, 312, 313, 314,
+        321, 500, 501, 502, 503, 504, 511, 520, 521, 522, 531, 600, 601, 602, 611, 612, 613, 615,
+        616, 620, 621, 622, 701, 711, 721, 731, 741, 751, 761, 762, 771, 781, 800, 801, 802, 803,
+        804,
+    ];
+
+    fn weather(id: u16, icon: &str) -> Vec<Weather> {
+        vec![Weather {
+            id,
+            description: String::new(),
+            icon: icon.to_owned(),
+        }]
+    }
+
+    fn alert(tags: &[&str]) -> Alert {
+        OwmAlert {
+            sender_name: "Ukrainian Hydrometeorological Center".to_owned(),
+            event: "Сильний вітер".to_owned(),
+            start: 1_792_465_200,
+            end: 1_792_519_200,
+            description: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+        .normalize()
+    }
+
+    #[test]
+    fn alert_tags() {
+        let wind = alert(&["Wind"]);
+        assert_eq!(wind.kind, Some("wind".to_owned()));
+        assert_eq!(wind.severity, Some("watch".to_owned()));
+        assert_eq!(wind.title, "Сильний вітер");
+        // the most severe tag wins
+        let storm = alert(&["wind", "Thunderstorm"]);
+        assert_eq!(storm.kind, Some("thunderstorm".to_owned()));
+        assert_eq!(storm.severity, Some("warning".to_owned()));
+        let unknown = alert(&["Meteorites"]);
+        assert_eq!(unknown.kind, None);
+        assert_eq!(unknown.severity, None);
+        assert_eq!(alert(&[]).kind, None);
+    }
+
+    #[test]
+    fn every_alert_tag_is_translated() {
+        let catalog = ::testing::catalog();
+        for (_, kind, severity) in ALERT_TAGS {
+            for lang in Lang::all() {
+                for key in &[format!("alert_{}", kind), format!("severity_{}", severity)] {
+                    assert_ne!(catalog.get(*lang, key), *key, "{} in {}", key, lang.code());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_condition_has_an_icon() {
+        for id in CONDITION_IDS {
+            for icon in &["01d", "01n"] {
+                assert!(
+                    condition(&weather(*id, icon)).0.is_some(),
+                    "no icon for {}",
+                    id
+                );
+            }
+        }
+        assert_eq!(condition(&weather(800, "01n")).0, Some(Icon::ClearNight));
+        assert_eq!(
+            condition(&weather(511, "13d")),
+            (Some(Icon::Sleet), Some(PrecipType::Sleet))
+        );
+        assert_eq!(condition(&[]), (None, None));
+    }
+}
@@ -0,0 +1,35 @@
+use i18n::Lang;
+use std::sync::Arc;
+use usage::UsageTracker;
+use weather::*;
+
+pub struct TrackedProvider {
+    inner: Box<WeatherProvider>,
+    usage: Arc<UsageTracker>,
+}
+
+impl TrackedProvider {
+    pub fn new(inner: Box<WeatherProvider>, usage: Arc<UsageTracker>) -> TrackedProvider {
+        TrackedProvider { inner, usage }
+    }
+}
+
+impl WeatherProvider for TrackedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn forecast(&self, lat: f64, lon: f64, lang: Lang) -> Result<Forecast, failure::Error> {
+        self.usage.track(self.inner.name(), "forecast", || {
+            self.inner.forecast(lat, lon, lang)
+        })
+    }
+
+    fn has_alerts(&self) -> bool {
+        self.inner.has_alerts()
+    }
+
+    fn is_localized(&self) -> bool {
+        self.inner.is_localized()
+    }
+}
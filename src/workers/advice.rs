@@ -0,0 +1,149 @@
+use config::Config;
+use i18n::Catalog;
+use weather::DataPoint;
+use workers::Prefs;
+
+static UMBRELLA_PROBABILITY: f64 = 0.6;
+static SUNSCREEN_UV: f64 = 6.0;
+static ICE_TEMPERATURE: f64 = 2.0;
+static LAYERS_SPREAD: f64 = 10.0;
+// precipitation less likely than this doesn't make roads icy
+static ICE_MIN_PROBABILITY: f64 = 0.3;
+
+pub struct Thresholds {
+    pub umbrella_probability: f64,
+    pub sunscreen_uv: f64,
+    pub ice_temperature: f64,
+    pub layers_spread: f64,
+}
+
+impl Thresholds {
+    pub fn of(config: &Config) -> Thresholds {
+        Thresholds {
+            umbrella_probability: config
+                .advice_umbrella_probability
+                .unwrap_or(UMBRELLA_PROBABILITY),
+            sunscreen_uv: config.advice_sunscreen_uv.unwrap_or(SUNSCREEN_UV),
+            ice_temperature: config.advice_ice_temperature.unwrap_or(ICE_TEMPERATURE),
+            layers_spread: config.advice_layers_spread.unwrap_or(LAYERS_SPREAD),
+        }
+    }
+}
+
+pub fn advise(thresholds: &Thresholds, day: &DataPoint) -> Vec<&'static str> {
+    let mut tips = Vec::new();
+    let probability = day.precip_probability.unwrap_or_default();
+    if day.precip_type.is_some() && probability >= thresholds.umbrella_probability {
+        tips.push("advice_umbrella");
+    }
+    if day.uv_index.unwrap_or_default() >= thresholds.sunscreen_uv {
+        tips.push("advice_sunscreen");
+    }
+    if let (Some(low), Some(high)) = (day.temperature_low, day.temperature_high) {
+        let near_zero = low <= thresholds.ice_temperature && high >= -thresholds.ice_temperature;
+        if day.precip_type.is_some() && probability >= ICE_MIN_PROBABILITY && near_zero {
+            tips.push("advice_ice");
+        }
+        if high - low >= thresholds.layers_spread {
+            tips.push("advice_layers");
+        }
+    }
+    tips
+}
+
+pub fn lines(
+    catalog: &Catalog,
+    thresholds: &Thresholds,
+    prefs: &Prefs,
+    day: &DataPoint,
+) -> Vec<String> {
+    if !prefs.fields.contains(&"advice") {
+        return Vec::new();
+    }
+    advise(thresholds, day)
+        .into_iter()
+        .map(|key| catalog.get(prefs.lang, key))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{catalog, fixture, prefs};
+
+    fn defaults() -> Thresholds {
+        Thresholds {
+            umbrella_probability: UMBRELLA_PROBABILITY,
+            sunscreen_uv: SUNSCREEN_UV,
+            ice_temperature: ICE_TEMPERATURE,
+            layers_spread: LAYERS_SPREAD,
+        }
+    }
+
+    // daily points of fixtures/default.json, starting with Monday, October 19
+    fn days() -> Vec<DataPoint> {
+        fixture().daily
+    }
+
+    #[test]
+    fn rain_near_zero() {
+        // 80% rain, 1..9.5℃
+        assert_eq!(
+            advise(&defaults(), &days()[1]),
+            vec!["advice_umbrella", "advice_ice"]
+        );
+    }
+
+    #[test]
+    fn cold_with_snow() {
+        // 60% snow, -2..4℃
+        assert_eq!(
+            advise(&defaults(), &days()[3]),
+            vec!["advice_umbrella", "advice_ice"]
+        );
+    }
+
+    #[test]
+    fn unlikely_snow_is_not_icy() {
+        // 20% snow, -1.5..5℃
+        assert!(advise(&defaults(), &days()[2]).is_empty());
+    }
+
+    #[test]
+    fn heat_and_strong_sun() {
+        let mut day = days()[5].clone();
+        day.uv_index = Some(7.0);
+        assert_eq!(advise(&defaults(), &day), vec!["advice_sunscreen"]);
+        assert!(advise(&defaults(), &days()[5]).is_empty());
+    }
+
+    #[test]
+    fn cold_morning_warm_afternoon() {
+        // 3..13℃
+        assert_eq!(advise(&defaults(), &days()[0]), vec!["advice_layers"]);
+    }
+
+    #[test]
+    fn configured_thresholds() {
+        let thresholds = Thresholds {
+            umbrella_probability: 0.9,
+            ..defaults()
+        };
+        assert_eq!(advise(&thresholds, &days()[1]), vec!["advice_ice"]);
+    }
+
+    #[test]
+    fn lines_unless_turned_off() {
+        let catalog = catalog();
+        let mut prefs = Prefs {
+            fields: vec!["advice"],
+            ..prefs()
+        };
+        assert_eq!(
+            lines(&catalog, &defaults(), &prefs, &days()[0]),
+            vec!["🧥 Cold morning, warm afternoon: dress in layers."]
+        );
+        prefs.fields.clear();
+        assert!(lines(&catalog, &defaults(), &prefs, &days()[0]).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use chrono::{DateTime, Utc};
+use config::Config;
+use i18n::Catalog;
+use weather;
+use weather::{DataPoint, Forecast};
+use workers::format;
+use workers::Prefs;
+
+static COMPARE_TEMPERATURE: f64 = 3.0;
+static COMPARE_WIND: f64 = 3.0;
+static COMPARE_PRECIP_PROBABILITY: f64 = 0.5;
+
+pub struct Thresholds {
+    pub temperature: f64,
+    pub wind: f64,
+    pub precip_probability: f64,
+}
+
+impl Thresholds {
+    pub fn of(config: &Config) -> Thresholds {
+        Thresholds {
+            temperature: config.compare_temperature.unwrap_or(COMPARE_TEMPERATURE),
+            wind: config.compare_wind.unwrap_or(COMPARE_WIND),
+            precip_probability: config
+                .compare_precip_probability
+                .unwrap_or(COMPARE_PRECIP_PROBABILITY),
+        }
+    }
+}
+
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// widened by the current conditions and the hours of today that have passed
+pub fn today(forecast: &Forecast, now: DateTime<Utc>) -> Option<DataPoint> {
+    let date = forecast.date_at(now);
+    let observed = forecast
+        .hourly
+        .iter()
+        .filter(|h| h.time <= now.timestamp() as u64 && forecast.date_of(h.time) == date)
+        .chain(forecast.current.iter());
+    let mut today = forecast.day(date).cloned();
+    for point in observed {
+        let day = today.get_or_insert_with(DataPoint::default);
+        day.temperature_high = max(day.temperature_high, point.temperature);
+        day.temperature_low = min(day.temperature_low, point.temperature);
+        day.wind_speed = max(day.wind_speed, point.wind_speed);
+    }
+    today
+}
+
+pub fn line(
+    catalog: &Catalog,
+    thresholds: &Thresholds,
+    prefs: &Prefs,
+    today: &DataPoint,
+    tomorrow: &DataPoint,
+) -> Option<String> {
+    let lang = prefs.lang;
+    let mut changes = Vec::new();
+    if let (Some(before), Some(after)) = (today.temperature_high, tomorrow.temperature_high) {
+        if (after - before).abs() >= thresholds.temperature {
+            let degrees = weather::UNITS.temperature(after, prefs.units)
+                - weather::UNITS.temperature(before, prefs.units);
+            changes.push(catalog.format(
+                lang,
+                if degrees > 0.0 {
+                    "compare_warmer"
+                } else {
+                    "compare_colder"
+                },
+                &[
+                    ("degrees", format!("{:.0}", degrees.abs()).as_str()),
+                    ("unit", prefs.units.temperature_symbol()),
+                ],
+            ));
+        }
+    }
+    let likely = |day: &DataPoint| {
+        day.precip_type.is_some()
+            && day.precip_probability.unwrap_or_default() >= thresholds.precip_probability
+    };
+    match (
+        likely(today),
+        likely(tomorrow),
+        tomorrow.precip_type.as_ref(),
+    ) {
+        (false, true, Some(precip)) => {
+            changes.push(catalog.get(lang, format::precip_key(precip)).to_lowercase())
+        }
+        (true, false, _) => changes.push(catalog.get(lang, "compare_dry")),
+        _ => {}
+    }
+    if let (Some(before), Some(after)) = (today.wind_speed, tomorrow.wind_speed) {
+        if after - before >= thresholds.wind {
+            changes.push(catalog.get(lang, "compare_windier"));
+        } else if before - after >= thresholds.wind {
+            changes.push(catalog.get(lang, "compare_calmer"));
+        }
+    }
+    if changes.is_empty() {
+        return None;
+    }
+    Some(catalog.format(
+        lang,
+        "compare_line",
+        &[("changes", changes.join(", ").as_str())],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use testing::{catalog, fixture, prefs, recorded};
+
+    fn compare(today: &DataPoint, tomorrow: &DataPoint) -> Option<String> {
+        let thresholds = Thresholds {
+            temperature: COMPARE_TEMPERATURE,
+            wind: COMPARE_WIND,
+            precip_probability: COMPARE_PRECIP_PROBABILITY,
+        };
+        line(&catalog(), &thresholds, &prefs(), today, tomorrow)
+    }
+
+    #[test]
+    fn today_at_the_recorded_time() {
+        let today = today(&fixture(), recorded()).unwrap();
+        assert_eq!(today.temperature_high, Some(13.0));
+        assert_eq!(today.temperature_low, Some(3.0));
+        assert_eq!(today.wind_speed, Some(4.1));
+    }
+
+    #[test]
+    fn today_widened_by_the_current_conditions() {
+        let mut forecast = fixture();
+        forecast.current.as_mut().unwrap().temperature = Some(15.0);
+        let today = today(&forecast, recorded()).unwrap();
+        assert_eq!(today.temperature_high, Some(15.0));
+    }
+
+    #[test]
+    fn colder_with_rain() {
+        let forecast = fixture();
+        let today = today(&forecast, recorded()).unwrap();
+        // 13℃ today, 9.5℃ and likely rain tomorrow
+        assert_eq!(
+            compare(&today, &forecast.daily[1]).unwrap(),
+            "Compared to today: 4℃ colder, rain."
+        );
+    }
+
+    #[test]
+    fn warmer() {
+        let daily = fixture().daily;
+        // Friday 7℃, Saturday 10℃
+        assert_eq!(
+            compare(&daily[4], &daily[5]).unwrap(),
+            "Compared to today: 3℃ warmer."
+        );
+    }
+
+    #[test]
+    fn same_temperature() {
+        let daily = fixture().daily;
+        // Saturday 10℃, Sunday 11℃ with about the same wind
+        assert_eq!(compare(&daily[5], &daily[6]), None);
+        // Wednesday 5℃, Thursday 4℃ with likely snow
+        assert_eq!(
+            compare(&daily[2], &daily[3]).unwrap(),
+            "Compared to today: snow."
+        );
+    }
+
+    #[test]
+    fn missing_day() {
+        let mut forecast = fixture();
+        forecast.current = None;
+        assert!(today(&forecast, recorded() + Duration::days(10)).is_none());
+        assert!(today(&Forecast::default(), recorded()).is_none());
+        assert_eq!(compare(&forecast.daily[0], &DataPoint::default()), None);
+    }
+}
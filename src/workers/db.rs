@@ -1,7 +1,18 @@
 use actix::Handler;
 use actix::Message;
+use chrono::Duration;
+use chrono_tz::Tz;
+use diesel::PgConnection;
+use i18n::Lang;
+use models::AlertRule;
+use models::NewAlertRule;
 use models::NewUser;
+use models::Subscriber;
 use models::User;
+use units::UnitSystem;
+use uuid::Uuid;
+use workers::details;
+use workers::rules;
 use workers::CustomError;
 use workers::WebWorker;
 
@@ -22,6 +33,21 @@ impl Handler<UserByEmail> for WebWorker {
     }
 }
 
+pub struct AllSubscribers;
+
+impl Message for AllSubscribers {
+    type Result = Result<Vec<Subscriber>, failure::Error>;
+}
+
+impl Handler<AllSubscribers> for WebWorker {
+    type Result = Result<Vec<Subscriber>, failure::Error>;
+
+    fn handle(&mut self, _: AllSubscribers, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        Ok(Subscriber::all(&conn)?)
+    }
+}
+
 pub struct RegisterUser(pub String);
 
 impl Message for RegisterUser {
@@ -48,3 +74,156 @@ impl Handler<RegisterUser> for WebWorker {
         res.map_err(|e| failure::Error::from(e))
     }
 }
+
+// minutes a code linking the web and viber accounts is valid
+pub static LINK_CODE_MINUTES: i64 = 60;
+
+pub struct LinkCode(pub String);
+
+impl Message for LinkCode {
+    type Result = Result<String, failure::Error>;
+}
+
+impl Handler<LinkCode> for WebWorker {
+    type Result = Result<String, failure::Error>;
+
+    fn handle(&mut self, msg: LinkCode, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        let user = user_by_email(msg.0.as_str(), &conn)?;
+        let code = Uuid::new_v4().simple().to_string()[..8].to_uppercase();
+        let expires = self.now().naive_utc() + Duration::minutes(LINK_CODE_MINUTES);
+        User::set_link_code(user.id, &code, expires, &conn)?;
+        Ok(code)
+    }
+}
+
+pub struct UpdateSettings {
+    pub email: String,
+    pub notify_time: Option<i16>,
+    pub timezone: Option<String>,
+    pub language: Option<Lang>,
+    pub units: Option<UnitSystem>,
+    pub forecast_fields: Option<String>,
+    pub rain_alerts: bool,
+}
+
+impl Message for UpdateSettings {
+    type Result = Result<(), failure::Error>;
+}
+
+impl Handler<UpdateSettings> for WebWorker {
+    type Result = Result<(), failure::Error>;
+
+    fn handle(&mut self, msg: UpdateSettings, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        let user = linked_user_by_email(msg.email.as_str(), &conn)?;
+        User::set_notify_time(user.id, msg.notify_time, &conn)?;
+        if let Some(ref tz) = msg.timezone {
+            tz.parse::<Tz>().map_err(|e| CustomError { msg: e })?;
+            User::set_timezone(user.id, tz, &conn)?;
+        }
+        if let Some(lang) = msg.language {
+            User::set_language(user.id, lang, &conn)?;
+        }
+        if let Some(units) = msg.units {
+            User::set_units(user.id, units, &conn)?;
+        }
+        if let Some(ref fields) = msg.forecast_fields {
+            let stored = if fields.trim().is_empty() {
+                None
+            } else {
+                let parsed = details::parse(fields).ok_or(CustomError {
+                    msg: format!("unknown forecast fields: {}", fields),
+                })?;
+                Some(parsed.join(","))
+            };
+            User::set_forecast_fields(user.id, stored.as_ref().map(|f| f.as_str()), &conn)?;
+        }
+        User::set_rain_alerts(user.id, msg.rain_alerts, &conn)?;
+        Ok(())
+    }
+}
+
+fn user_by_email(email: &str, conn: &PgConnection) -> Result<User, failure::Error> {
+    User::by_email(email, conn).ok_or(failure::Error::from(CustomError {
+        msg: "no such user".to_owned(),
+    }))
+}
+
+// settings only take effect for users the bot can reach in viber
+fn linked_user_by_email(email: &str, conn: &PgConnection) -> Result<User, failure::Error> {
+    let user = user_by_email(email, conn)?;
+    if user.viber_id.is_none() {
+        return Err(failure::Error::from(CustomError {
+            msg: "the viber account is not linked".to_owned(),
+        }));
+    }
+    Ok(user)
+}
+
+pub struct RulesByEmail(pub String);
+
+impl Message for RulesByEmail {
+    type Result = Result<Vec<AlertRule>, failure::Error>;
+}
+
+impl Handler<RulesByEmail> for WebWorker {
+    type Result = Result<Vec<AlertRule>, failure::Error>;
+
+    fn handle(&mut self, msg: RulesByEmail, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        let user = user_by_email(msg.0.as_str(), &conn)?;
+        Ok(AlertRule::of_user(user.id, &conn)?)
+    }
+}
+
+pub struct AddRule {
+    pub email: String,
+    pub rule: String,
+}
+
+impl Message for AddRule {
+    type Result = Result<AlertRule, failure::Error>;
+}
+
+impl Handler<AddRule> for WebWorker {
+    type Result = Result<AlertRule, failure::Error>;
+
+    fn handle(&mut self, msg: AddRule, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        let user = linked_user_by_email(msg.email.as_str(), &conn)?;
+        let (field, op, value) = rules::parse(&msg.rule).ok_or(CustomError {
+            msg: format!("invalid rule: {}", msg.rule),
+        })?;
+        Ok(AlertRule::insert(
+            NewAlertRule {
+                user_id: user.id,
+                field: &field,
+                op: &op,
+                value,
+                units: user.units().code(),
+            },
+            &conn,
+        )?)
+    }
+}
+
+pub struct DeleteRule {
+    pub email: String,
+    pub id: i32,
+}
+
+impl Message for DeleteRule {
+    type Result = Result<(), failure::Error>;
+}
+
+impl Handler<DeleteRule> for WebWorker {
+    type Result = Result<(), failure::Error>;
+
+    fn handle(&mut self, msg: DeleteRule, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        let user = user_by_email(msg.email.as_str(), &conn)?;
+        AlertRule::delete(msg.id, user.id, &conn)?;
+        Ok(())
+    }
+}
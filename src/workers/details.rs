@@ -0,0 +1,110 @@
+use chrono::*;
+use chrono_tz::Tz;
+use i18n::Catalog;
+use weather;
+use weather::DataPoint;
+use workers::Prefs;
+
+// "advice" stands for the tips of `advice::lines`
+pub static FIELDS: &[&str] = &[
+    "wind", "humidity", "pressure", "uv", "clouds", "sun", "moon", "advice",
+];
+pub static DEFAULT_FIELDS: &[&str] = &["wind", "humidity", "sun", "advice"];
+
+pub fn parse(text: &str) -> Option<Vec<&'static str>> {
+    let mut fields = Vec::new();
+    let names = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty() && *name != "none");
+    for name in names {
+        let field = *FIELDS.iter().find(|f| **f == name.to_lowercase())?;
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    Some(fields)
+}
+
+// where the wind blows to, the bearing being where it comes from
+fn wind_arrow(bearing: f64) -> &'static str {
+    let arrows = ["↓", "↙", "←", "↖", "↑", "↗", "→", "↘"];
+    arrows[((bearing.rem_euclid(360.0) + 22.5) / 45.0) as usize % 8]
+}
+
+fn moon_phase(phase: f64) -> &'static str {
+    let phases = ["🌑", "🌒", "🌓", "🌔", "🌕", "🌖", "🌗", "🌘"];
+    phases[(phase.rem_euclid(1.0) * 8.0).round() as usize % 8]
+}
+
+fn wind(catalog: &Catalog, prefs: &Prefs, dp: &DataPoint) -> Option<String> {
+    let speed = weather::UNITS.speed(dp.wind_speed?, prefs.units);
+    let unit = prefs.units.speed_symbol();
+    let direction = dp.wind_bearing.map(wind_arrow).unwrap_or_default();
+    Some(match dp.wind_gust {
+        Some(gust) => catalog.format(
+            prefs.lang,
+            "detail_wind_gusts",
+            &[
+                ("direction", direction),
+                ("speed", format!("{:.0}", speed).as_str()),
+                (
+                    "gust",
+                    format!("{:.0}", weather::UNITS.speed(gust, prefs.units)).as_str(),
+                ),
+                ("unit", unit),
+            ],
+        ),
+        None => catalog.format(
+            prefs.lang,
+            "detail_wind",
+            &[
+                ("direction", direction),
+                ("speed", format!("{:.0}", speed).as_str()),
+                ("unit", unit),
+            ],
+        ),
+    })
+}
+
+fn line(catalog: &Catalog, prefs: &Prefs, tz: &Tz, dp: &DataPoint, field: &str) -> Option<String> {
+    let simple =
+        |key: &str, value: String| catalog.format(prefs.lang, key, &[("value", value.as_str())]);
+    match field {
+        "wind" => wind(catalog, prefs, dp),
+        "humidity" => dp
+            .humidity
+            .map(|h| simple("detail_humidity", format!("{:.0}", h * 100.0))),
+        "pressure" => dp
+            .pressure
+            .map(|p| simple("detail_pressure", format!("{:.0}", p))),
+        "uv" => dp
+            .uv_index
+            .map(|uv| simple("detail_uv", format!("{:.0}", uv))),
+        "clouds" => dp
+            .cloud_cover
+            .map(|c| simple("detail_clouds", format!("{:.0}", c * 100.0))),
+        "sun" => {
+            let time = |t: u64| tz.timestamp(t as i64, 0).format("%H:%M").to_string();
+            Some(catalog.format(
+                prefs.lang,
+                "detail_sun",
+                &[
+                    ("sunrise", time(dp.sunrise_time?).as_str()),
+                    ("sunset", time(dp.sunset_time?).as_str()),
+                ],
+            ))
+        }
+        "moon" => dp
+            .moon_phase
+            .map(|m| simple("detail_moon", moon_phase(m).to_owned())),
+        _ => None,
+    }
+}
+
+pub fn lines(catalog: &Catalog, prefs: &Prefs, tz: &Tz, dp: &DataPoint) -> Vec<String> {
+    prefs
+        .fields
+        .iter()
+        .filter_map(|field| line(catalog, prefs, tz, dp, field))
+        .collect()
+}
@@ -0,0 +1,335 @@
+use chrono::*;
+use chrono_tz::Tz;
+use common::messages::ForecastKind;
+use i18n::Catalog;
+use weather;
+use weather::{DataPoint, Forecast, PrecipType};
+use workers::ForecastError;
+use workers::Prefs;
+
+// hourly points closer than this count as the current conditions
+static CURRENT_MAX_AGE: i64 = 60 * 60;
+static TODAY_STEP_HOURS: usize = 3;
+static HOURLY_HOURS: usize = 48;
+static WEEK_DAYS: usize = 7;
+// less likely precipitation is not mentioned in the hourly lines
+static PRECIP_MIN_PROBABILITY: f64 = 0.1;
+
+fn local_time(tz: &Tz, time: u64) -> DateTime<Tz> {
+    tz.timestamp(time as i64, 0)
+}
+
+fn weekday(catalog: &Catalog, prefs: &Prefs, date: &DateTime<Tz>) -> String {
+    catalog.get(
+        prefs.lang,
+        &format!("weekday_{}", date.weekday().number_from_monday()),
+    )
+}
+
+pub fn summary(catalog: &Catalog, prefs: &Prefs, data_point: &DataPoint) -> Option<String> {
+    let text = data_point.summary.clone().or_else(|| {
+        data_point
+            .icon
+            .map(|icon| catalog.get(prefs.lang, &format!("icon_{}", icon.code())))
+    })?;
+    Some(match data_point.icon {
+        Some(icon) => format!("{} {}", icon.emoji(), text),
+        None => text,
+    })
+}
+
+fn temperature(prefs: &Prefs, value: f64) -> String {
+    format!(
+        "{:.0}{}",
+        weather::UNITS.temperature(value, prefs.units),
+        prefs.units.temperature_symbol()
+    )
+}
+
+pub fn precip_key(precip_type: &PrecipType) -> &'static str {
+    match precip_type {
+        PrecipType::Rain => "precip_rain",
+        PrecipType::Snow => "precip_snow",
+        PrecipType::Sleet => "precip_sleet",
+    }
+}
+
+fn precip_name(catalog: &Catalog, prefs: &Prefs, data_point: &DataPoint) -> Option<String> {
+    let probability = data_point.precip_probability.unwrap_or_default();
+    if probability < PRECIP_MIN_PROBABILITY {
+        return None;
+    }
+    let key = precip_key(data_point.precip_type.as_ref()?);
+    Some(format!(
+        "{} {:.0}%",
+        catalog.get(prefs.lang, key),
+        probability * 100.0
+    ))
+}
+
+fn hour_line(catalog: &Catalog, prefs: &Prefs, tz: &Tz, data_point: &DataPoint) -> String {
+    let mut line = format!(
+        "{} {}",
+        local_time(tz, data_point.time).format("%H:%M"),
+        data_point
+            .temperature
+            .map(|t| temperature(prefs, t))
+            .unwrap_or_else(|| "-".to_owned())
+    );
+    if let Some(summary) = summary(catalog, prefs, data_point) {
+        line.push_str(" ");
+        line.push_str(&summary);
+    }
+    if let Some(precip) = precip_name(catalog, prefs, data_point) {
+        line.push_str(", ");
+        line.push_str(&precip);
+    }
+    line
+}
+
+// falls back to the nearest hourly point when `currently` is too old, e.g. cached hours ago
+pub fn format_current(
+    catalog: &Catalog,
+    prefs: &Prefs,
+    resp: &Forecast,
+    now: DateTime<Utc>,
+) -> Result<String, failure::Error> {
+    let now_ts = now.timestamp();
+    let is_recent = |dp: &DataPoint| (dp.time as i64 - now_ts).abs() < CURRENT_MAX_AGE;
+    let current = match resp.current {
+        Some(ref dp) if is_recent(dp) => dp,
+        _ => resp
+            .hourly
+            .iter()
+            .filter(|dp| is_recent(dp))
+            .min_by_key(|dp| (dp.time as i64 - now_ts).abs())
+            .ok_or(ForecastError::Missing {
+                name: "current".to_owned(),
+            })?,
+    };
+    let lang = prefs.lang;
+    let mut lines = vec![catalog.get(lang, "forecast_now")];
+    lines.push(summary(catalog, prefs, current).unwrap_or_default());
+    lines.push(
+        catalog.format(
+            lang,
+            "current_temperature",
+            &[
+                (
+                    "temperature",
+                    current
+                        .temperature
+                        .map(|t| temperature(prefs, t))
+                        .unwrap_or_default()
+                        .as_str(),
+                ),
+                (
+                    "apparent",
+                    current
+                        .apparent_temperature
+                        .map(|t| temperature(prefs, t))
+                        .unwrap_or_default()
+                        .as_str(),
+                ),
+            ],
+        ),
+    );
+    if let Some(speed) = current.wind_speed {
+        lines.push(catalog.format(
+            lang,
+            "wind",
+            &[
+                (
+                    "speed",
+                    format!("{:.1}", weather::UNITS.speed(speed, prefs.units)).as_str(),
+                ),
+                ("unit", prefs.units.speed_symbol()),
+            ],
+        ));
+    }
+    if let Some(precip) = precip_name(catalog, prefs, current) {
+        lines.push(precip);
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn format_today(
+    catalog: &Catalog,
+    prefs: &Prefs,
+    resp: &Forecast,
+    now: DateTime<Utc>,
+) -> Result<String, failure::Error> {
+    let tz = resp.tz();
+    let today = now.with_timezone(&tz).date();
+    let mut lines = vec![catalog.format(
+        prefs.lang,
+        "forecast_today",
+        &[("date", today.format("%d.%m").to_string().as_str())],
+    )];
+    lines.extend(
+        resp.hourly
+            .iter()
+            .filter(|dp| dp.time as i64 >= now.timestamp())
+            .take_while(|dp| local_time(&tz, dp.time).date() == today)
+            .step_by(TODAY_STEP_HOURS)
+            .map(|dp| hour_line(catalog, prefs, &tz, dp)),
+    );
+    Ok(lines.join("\n"))
+}
+
+pub fn format_hourly(
+    catalog: &Catalog,
+    prefs: &Prefs,
+    resp: &Forecast,
+    now: DateTime<Utc>,
+) -> Result<String, failure::Error> {
+    let tz = resp.tz();
+    let mut lines = vec![catalog.get(prefs.lang, "forecast_hourly")];
+    let mut day = None;
+    for dp in resp
+        .hourly
+        .iter()
+        .filter(|dp| dp.time as i64 >= now.timestamp())
+        .take(HOURLY_HOURS)
+    {
+        let local = local_time(&tz, dp.time);
+        if day != Some(local.date()) {
+            day = Some(local.date());
+            lines.push(format!(
+                "{} {}",
+                weekday(catalog, prefs, &local),
+                local.format("%d.%m")
+            ));
+        }
+        lines.push(hour_line(catalog, prefs, &tz, dp));
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn format_week(
+    catalog: &Catalog,
+    prefs: &Prefs,
+    resp: &Forecast,
+    now: DateTime<Utc>,
+) -> Result<String, failure::Error> {
+    let tz = resp.tz();
+    let today = now.with_timezone(&tz).date();
+    let mut lines = vec![catalog.get(prefs.lang, "forecast_week")];
+    for dp in resp
+        .daily
+        .iter()
+        .filter(|dp| local_time(&tz, dp.time).date() >= today)
+        .take(WEEK_DAYS)
+    {
+        let local = local_time(&tz, dp.time);
+        let mut line = format!(
+            "{} {}: {}..{}",
+            weekday(catalog, prefs, &local),
+            local.format("%d.%m"),
+            dp.temperature_low
+                .map(|t| temperature(prefs, t))
+                .unwrap_or_default(),
+            dp.temperature_high
+                .map(|t| temperature(prefs, t))
+                .unwrap_or_default()
+        );
+        if let Some(summary) = summary(catalog, prefs, dp) {
+            line.push_str(" ");
+            line.push_str(&summary);
+        }
+        if let Some(precip) = precip_name(catalog, prefs, dp) {
+            line.push_str(", ");
+            line.push_str(&precip);
+        }
+        lines.push(line);
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn format_kind(
+    catalog: &Catalog,
+    prefs: &Prefs,
+    kind: ForecastKind,
+    resp: &Forecast,
+    now: DateTime<Utc>,
+) -> Result<String, failure::Error> {
+    match kind {
+        ForecastKind::Now => format_current(catalog, prefs, resp, now),
+        ForecastKind::Today => format_today(catalog, prefs, resp, now),
+        ForecastKind::Hourly => format_hourly(catalog, prefs, resp, now),
+        ForecastKind::Week => format_week(catalog, prefs, resp, now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{catalog, fixture, prefs, recorded};
+
+    #[test]
+    fn current_conditions() {
+        let text = format_current(&catalog(), &prefs(), &fixture(), recorded()).unwrap();
+        assert_eq!(
+            text,
+            "Now:\n⛅ Partly cloudy\nTemperature: 8℃, feels like 6℃\nWind: 3.8 m/s"
+        );
+    }
+
+    #[test]
+    fn current_falls_back_to_the_nearest_hour() {
+        let later = recorded() + Duration::hours(5);
+        let text = format_current(&catalog(), &prefs(), &fixture(), later).unwrap();
+        assert!(text.contains("Temperature: 13℃, feels like 11℃"));
+        assert!(text.contains("Wind: 4.2 m/s"));
+    }
+
+    #[test]
+    fn current_missing() {
+        let result = format_current(&catalog(), &prefs(), &Forecast::default(), recorded());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rest_of_today() {
+        let text = format_today(&catalog(), &prefs(), &fixture(), recorded()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "Today 19.10:");
+        assert_eq!(lines[1], "09:00 8℃ ⛅ Partly cloudy");
+        assert_eq!(lines[3], "15:00 13℃ ⛅ Partly cloudy");
+        assert_eq!(lines[5], "21:00 8℃ ☁️🌙 Partly cloudy");
+    }
+
+    #[test]
+    fn hourly_with_day_headers() {
+        let text = format_hourly(&catalog(), &prefs(), &fixture(), recorded()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        // the fixture ends at midnight of the second day, 15 + 24 hours from now
+        assert_eq!(lines.len(), 1 + 1 + 15 + 1 + 24);
+        assert_eq!(lines[0], "Forecast for 48 hours:");
+        assert_eq!(lines[1], "Mon 19.10");
+        assert_eq!(lines[2], "09:00 8℃ ⛅ Partly cloudy");
+        assert_eq!(lines[17], "Tue 20.10");
+        assert!(lines.contains(&"09:00 6℃ ☁️ Cloudy, Rain 30%"));
+        assert!(lines.contains(&"15:00 11℃ 🌧 Rain, Rain 70%"));
+    }
+
+    #[test]
+    fn week_from_today() {
+        let text = format_week(&catalog(), &prefs(), &fixture(), recorded()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 7);
+        assert_eq!(lines[0], "Forecast for 7 days:");
+        assert_eq!(lines[1], "Mon 19.10: 3℃..13℃ ⛅ Partly cloudy");
+        assert_eq!(lines[4], "Thu 22.10: -2℃..4℃ ❄️ Snow, Snow 60%");
+        assert_eq!(lines[7], "Sun 25.10: 3℃..11℃ 🌫 Fog");
+    }
+
+    #[test]
+    fn week_skips_past_days() {
+        let tomorrow = recorded() + Duration::days(1);
+        let text = format_week(&catalog(), &prefs(), &fixture(), tomorrow).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[1].starts_with("Tue 20.10: "));
+    }
+}
@@ -0,0 +1,139 @@
+use actix::Handler;
+use actix::Message;
+use chrono::Utc;
+use chrono_tz;
+use chrono_tz::Tz;
+use config::Config;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use models::JobRun;
+use scheduler::{Misfire, Registry, Schedule};
+use workers::WebWorker;
+
+static RAIN_CHECK_INTERVAL: i64 = 10;
+static ALERTS_CHECK_INTERVAL: i64 = 5;
+static RECENT_RUNS: i64 = 10;
+// the lease of an instance gone in the middle of a job expires after this time
+pub static LEASE_SECONDS: i64 = 30 * 60;
+// a running job renews its lease this often
+pub static LEASE_RENEW_SECONDS: u64 = 5 * 60;
+
+pub fn instance_id(config: &Config) -> String {
+    config
+        .instance_id
+        .clone()
+        .or_else(|| std::env::var("DYNO").ok())
+        .unwrap_or_else(|| format!("{}-{}", std::process::id(), Utc::now().timestamp_nanos()))
+}
+
+fn defaults(config: &Config) -> Vec<(&'static str, String, Misfire)> {
+    vec![
+        // users have their own delivery times, checked every minute
+        ("forecast_broadcast", "every 1m".to_owned(), Misfire::Skip),
+        ("btc_price", "daily 7:00".to_owned(), Misfire::RunOnce),
+        (
+            "rain_check",
+            format!(
+                "every {}m",
+                config.rain_check_interval.unwrap_or(RAIN_CHECK_INTERVAL)
+            ),
+            Misfire::Skip,
+        ),
+        // a rule fires once a day, new rules are picked up on the next run
+        ("rules_check", "every 30m".to_owned(), Misfire::Skip),
+        ("cleanup", "daily 4:00".to_owned(), Misfire::RunOnce),
+        (
+            "alerts_check",
+            format!(
+                "every {}m",
+                config
+                    .alerts_check_interval
+                    .unwrap_or(ALERTS_CHECK_INTERVAL)
+            ),
+            Misfire::Skip,
+        ),
+    ]
+}
+
+pub fn timezone(config: &Config) -> Tz {
+    config
+        .scheduler_timezone
+        .as_ref()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::Europe::Kiev)
+}
+
+pub fn registry(
+    config: &Config,
+    pool: &Pool<ConnectionManager<PgConnection>>,
+    now: i64,
+) -> Registry {
+    let tz = timezone(config);
+    let mut registry = Registry::default();
+    for (name, default_schedule, default_misfire) in defaults(config) {
+        let mut text = default_schedule;
+        if let Some(configured) = config.job_schedules.as_ref().and_then(|s| s.get(name)) {
+            if Schedule::parse(configured, tz).is_some() {
+                text = configured.clone();
+            } else {
+                error!("Invalid schedule of the {} job: {}", name, configured);
+            }
+        }
+        let schedule = Schedule::parse(&text, tz).expect("invalid default schedule");
+        let misfire = match config.job_misfires.as_ref().and_then(|m| m.get(name)) {
+            Some(code) => Misfire::from_code(code).unwrap_or_else(|| {
+                error!("Invalid misfire policy of the {} job: {}", name, code);
+                default_misfire
+            }),
+            None => default_misfire,
+        };
+        registry.register(name, &text, schedule, misfire, pool, now);
+    }
+    registry
+}
+
+pub struct RecentRuns(pub String);
+
+impl Message for RecentRuns {
+    type Result = Result<Vec<JobRun>, failure::Error>;
+}
+
+impl Handler<RecentRuns> for WebWorker {
+    type Result = Result<Vec<JobRun>, failure::Error>;
+
+    fn handle(&mut self, msg: RecentRuns, _: &mut Self::Context) -> Self::Result {
+        let conn = self.app_state.pool.get()?;
+        Ok(JobRun::recent(&msg.0, RECENT_RUNS, &conn)?)
+    }
+}
+
+pub struct RunJob(pub String);
+
+impl Message for RunJob {
+    type Result = Result<(), failure::Error>;
+}
+
+impl Handler<RunJob> for WebWorker {
+    type Result = Result<(), failure::Error>;
+
+    fn handle(&mut self, msg: RunJob, _: &mut Self::Context) -> Self::Result {
+        self.run_job_now(&msg.0)
+    }
+}
+
+pub struct PauseJob {
+    pub name: String,
+    pub paused: bool,
+}
+
+impl Message for PauseJob {
+    type Result = Result<(), failure::Error>;
+}
+
+impl Handler<PauseJob> for WebWorker {
+    type Result = Result<(), failure::Error>;
+
+    fn handle(&mut self, msg: PauseJob, _: &mut Self::Context) -> Self::Result {
+        self.pause_job(&msg.name, msg.paused)
+    }
+}
@@ -1,31 +1,53 @@
 use actix_web::HttpMessage;
 use actix_web::*;
 use bitcoin;
-use chrono::FixedOffset;
 use chrono::*;
+use chrono_tz;
+use chrono_tz::Tz;
 use common;
-use forecast::ApiResponse;
-use forecast::*;
+use common::messages::ForecastKind;
 use futures::Future;
+use i18n;
+use models::{
+    AlertRule, JobLease, JobRun, NewAlertRule, NewJobRun, NewSentAlert, QuotaUsage, SentAlert,
+    Subscriber, User,
+};
+use scheduler;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
 use std::io::Write;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use units::UnitSystem;
+use usage;
 use viber;
+use viber::messages::Keyboard;
 use viber::messages::Member;
+use weather;
+use weather::{DataPoint, Forecast};
 use AppStateType;
-use ServiceQuota;
 
-static LATITUDE: f64 = 50.4501;
-static LONGITUDE: f64 = 30.5234;
+static RAIN_ALERT_THRESHOLD: f64 = 0.5;
+static RAIN_ALERT_COOLDOWN: i64 = 180;
+static JOB_RUNS_KEEP_DAYS: i64 = 7;
+// the admin's daily forecast until they choose a time, 16:00 like the old broadcast
+static ADMIN_NOTIFY_TIME: i16 = 16 * 60;
 
+pub mod advice;
+pub mod compare;
 pub mod db;
+pub mod details;
+pub mod format;
+pub mod jobs;
+pub mod quota;
+pub mod rain;
+pub mod rules;
+pub mod severe;
 
 #[derive(Debug, Fail)]
-enum JsonError {
-    #[fail(display = "field is missing: {}", name)]
-    MissingField { name: String },
-    #[fail(display = "error accessing array")]
-    ArrayIndex,
+enum ForecastError {
+    #[fail(display = "forecast data is missing: {}", name)]
+    Missing { name: String },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -45,10 +67,42 @@ pub struct CustomError {
     msg: String,
 }
 
+#[derive(Clone)]
+pub struct Prefs {
+    pub lang: i18n::Lang,
+    pub units: UnitSystem,
+    pub fields: Vec<&'static str>,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Prefs {
+            lang: i18n::Lang::default(),
+            units: UnitSystem::default(),
+            fields: details::DEFAULT_FIELDS.to_vec(),
+        }
+    }
+}
+
+impl Prefs {
+    pub fn of(user: &User) -> Prefs {
+        Prefs {
+            lang: user.lang(),
+            units: user.units(),
+            fields: user
+                .forecast_fields
+                .as_ref()
+                .and_then(|f| details::parse(f))
+                .unwrap_or_else(|| details::DEFAULT_FIELDS.to_vec()),
+        }
+    }
+}
+
 pub struct WebWorker {
     pub app_state: AppStateType,
-    pub last_response: Option<ApiResponse>,
+    pub last_response: Option<Forecast>,
     pub last_subscriber_update: i64,
+    pub instance: String,
     pub viber: viber::Viber,
 }
 
@@ -56,10 +110,12 @@ impl WebWorker {
     pub fn new(app_state: AppStateType) -> WebWorker {
         let api = app_state.config.viber_api_key.clone().unwrap();
         let admin = app_state.config.admin_id.clone().unwrap();
+        let instance = jobs::instance_id(&app_state.config);
         WebWorker {
             app_state,
             last_response: None,
             last_subscriber_update: 0,
+            instance,
             viber: viber::Viber::new(api, admin),
         }
     }
@@ -68,57 +124,281 @@ impl WebWorker {
 impl WebWorker {
     pub fn tick(&mut self) {
         match self.inquire_if_needed() {
-            Err(e) => {
-                error!("Error inquiring workers forecast. {}", e.as_fail());
-            }
-            Ok(success) => {
-                if success {
-                    let mut state = &self.app_state;
-                    self.viber
-                        .update_subscribers(&mut state.subscribers.write().unwrap())
-                        .map_err(|e| {
-                            error!("Failed to read subscribers of the viber chat. {:?}", e);
-                        })
-                        .unwrap_or_default();;
-                    let mut quota = self.app_state.quota.write().unwrap();
-                    quota.clear();
-                    let members = self.app_state.subscribers.read();
-                    let iter: &Vec<Member> = members.as_ref().unwrap().as_ref();
-                    for ref subscriber in iter {
-                        quota.insert(subscriber.id.clone(), ServiceQuota::default());
+            Err(e) => error!("Error inquiring workers forecast. {}", e.as_fail()),
+            Ok(true) => self
+                .refresh_subscribers()
+                .map_err(|e| {
+                    error!("Failed to read subscribers of the viber chat. {:?}", e);
+                })
+                .unwrap_or_default(),
+            Ok(false) => {}
+        }
+        self.run_due_jobs();
+        self.send_budget_warnings()
+            .map_err(|e| error!("Failed to send budget warnings. {:?}", e))
+            .unwrap_or_default();
+    }
+
+    // delivered alerts are remembered, so a warning reaches each user once
+    fn send_severe_alerts(&self) -> Result<usize, failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let now = self.now();
+        let mut sent = 0;
+        SentAlert::delete_expired(now.naive_utc(), &conn)?;
+        // users living close to each other and speaking the same language share a request
+        let mut groups: HashMap<(i64, i64, i18n::Lang), Vec<User>> = HashMap::new();
+        for user in User::monitored(&conn)? {
+            let (lat, lon) = user
+                .location()
+                .unwrap_or_else(|| self.app_state.config.default_location());
+            groups
+                .entry((
+                    weather::location_key(lat),
+                    weather::location_key(lon),
+                    user.lang(),
+                ))
+                .or_insert_with(Vec::new)
+                .push(user);
+        }
+        for (_, users) in groups {
+            let (lat, lon) = users[0]
+                .location()
+                .unwrap_or_else(|| self.app_state.config.default_location());
+            let resp = match self.inquire(lat, lon, users[0].lang()) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    error!("Error while requesting alerts: {:?}", e.as_fail());
+                    continue;
+                }
+            };
+            for alert in &resp.alerts {
+                if alert.expires() < now.timestamp() as u64 {
+                    continue;
+                }
+                for user in &users {
+                    let viber_id = user.viber_id.as_ref().unwrap();
+                    if SentAlert::exists(viber_id, &alert.id, alert.expires_at(), &conn)? {
+                        continue;
                     }
+                    let prefs = Prefs::of(user);
+                    let text = severe::format(
+                        alert,
+                        &self.app_state.i18n,
+                        &self.app_state.messages,
+                        &prefs,
+                        &resp.tz(),
+                    )?;
+                    self.reply(viber_id, prefs.lang, &text)?;
+                    sent += 1;
+                    SentAlert::insert(
+                        NewSentAlert {
+                            viber_id,
+                            alert_id: &alert.id,
+                            expires: alert.expires_at(),
+                        },
+                        &conn,
+                    )?;
                 }
             }
+        }
+        Ok(sent)
+    }
+
+    fn send_rain_alerts(&self) -> Result<usize, failure::Error> {
+        let config = &self.app_state.config;
+        let threshold = config.rain_alert_threshold.unwrap_or(RAIN_ALERT_THRESHOLD);
+        let cooldown =
+            chrono::Duration::minutes(config.rain_alert_cooldown.unwrap_or(RAIN_ALERT_COOLDOWN));
+        let conn = self.app_state.pool.get()?;
+        let now = self.now();
+        let mut sent = 0;
+        for user in User::with_rain_alerts(&conn)? {
+            if let Some(last) = user.last_rain_alert {
+                if now.naive_utc().signed_duration_since(last) < cooldown {
+                    continue;
+                }
+            }
+            let (lat, lon) = user.location().unwrap();
+            let prefs = Prefs::of(&user);
+            let resp = match self.inquire(lat, lon, prefs.lang) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    error!("Error while requesting forecast: {:?}", e.as_fail());
+                    continue;
+                }
+            };
+            if let Some(seconds) = rain::seconds_until_rain(&resp, now.timestamp(), threshold) {
+                let text = self.app_state.i18n.format(
+                    prefs.lang,
+                    "rain_soon",
+                    &[("minutes", (seconds / 60).to_string().as_str())],
+                );
+                self.reply(user.viber_id.as_ref().unwrap(), prefs.lang, &text)?;
+                sent += 1;
+                User::set_last_rain_alert(user.id, now.naive_utc(), &conn)?;
+            }
+        }
+        Ok(sent)
+    }
+
+    pub fn set_rain_alerts(&self, user_id: &str, enabled: bool) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        User::set_rain_alerts(user.id, enabled, &conn)?;
+        let lang = user.lang();
+        let key = match (enabled, user.location()) {
+            (false, _) => "rain_alerts_off",
+            (true, Some(_)) => "rain_alerts_on",
+            (true, None) => "rain_alerts_no_location",
         };
-        self.try_broadcast();
-    }
-
-    fn is_outdated(&self) -> Result<bool, failure::Error> {
-        match self.last_response {
-            None => Ok(true),
-            Some(ref resp) => {
-                let today = Utc::now().with_timezone(&FixedOffset::east(2 * 3600));
-                // check if the second daily forecast is for today:
-                let dt = {
-                    let daily = resp.daily.as_ref().ok_or(JsonError::MissingField {
-                        name: "daily".to_owned(),
-                    })?;
-                    let first = daily.data.get(1).ok_or(JsonError::ArrayIndex)?;
-                    // debug!("daily data: {:?}", daily);
-                    Utc.timestamp(first.time as i64, 0)
-                };
-                Ok(dt.day() != today.day())
+        self.reply(user_id, lang, &self.app_state.i18n.get(lang, key))
+    }
+
+    // a rule fires at most once a day
+    fn evaluate_rules(&self) -> Result<usize, failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let rules = AlertRule::all(&conn)?;
+        let mut sent = 0;
+        let ids: Vec<i32> = rules.iter().map(|rule| rule.user_id).collect();
+        let users: HashMap<i32, User> = User::by_ids(&ids, &conn)?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+        let now = self.now();
+        // forecasts for home locations, the default city uses the cached one
+        let mut forecasts: HashMap<(i64, i64), Option<Forecast>> = HashMap::new();
+        for rule in rules {
+            let user = match users.get(&rule.user_id) {
+                Some(user) if user.viber_id.is_some() => user,
+                _ => continue,
+            };
+            // once a day in the user's timezone
+            let today = now.with_timezone(&user.tz()).date().naive_local();
+            if rule.last_triggered == Some(today) {
+                continue;
             }
+            let forecast = match user.location() {
+                None => &self.last_response,
+                Some((lat, lon)) => &*forecasts
+                    .entry((weather::location_key(lat), weather::location_key(lon)))
+                    .or_insert_with(|| {
+                        self.inquire(lat, lon, i18n::Lang::default())
+                            .map_err(|e| {
+                                error!("Error while requesting forecast: {:?}", e.as_fail())
+                            })
+                            .ok()
+                    }),
+            };
+            let day = match self.tomorrow(forecast) {
+                Ok(day) => day,
+                Err(_) => continue,
+            };
+            let prefs = Prefs::of(user);
+            let actual = match rules::value(&rule.field, day, weather::UNITS, rule.units()) {
+                Some(actual) => actual,
+                None => continue,
+            };
+            if !rules::matches(&rule.op, actual, rule.value) {
+                continue;
+            }
+            let text = self.app_state.i18n.format(
+                prefs.lang,
+                "rule_triggered",
+                &[
+                    ("rule", rule.describe().as_str()),
+                    ("actual", format!("{:.1}", actual).as_str()),
+                    ("unit", rules::unit(&rule.field, rule.units())),
+                ],
+            );
+            self.reply(user.viber_id.as_ref().unwrap(), prefs.lang, &text)?;
+            sent += 1;
+            AlertRule::set_last_triggered(rule.id, today, &conn)?;
         }
+        Ok(sent)
+    }
+
+    // the value is in the user's units, kept with the rule
+    pub fn add_rule(&self, user_id: &str, text: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let reply = match rules::parse(text) {
+            Some((field, op, value)) => {
+                let rule = AlertRule::insert(
+                    NewAlertRule {
+                        user_id: user.id,
+                        field: &field,
+                        op: &op,
+                        value,
+                        units: user.units().code(),
+                    },
+                    &conn,
+                )?;
+                self.app_state.i18n.format(
+                    lang,
+                    "rule_added",
+                    &[("rule", rule.describe().as_str())],
+                )
+            }
+            None => self.app_state.i18n.format(
+                lang,
+                "rule_invalid",
+                &[("fields", rules::FIELDS.join(", ").as_str())],
+            ),
+        };
+        self.reply(user_id, lang, &reply)
+    }
+
+    pub fn list_rules(&self, user_id: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let rules = AlertRule::of_user(user.id, &conn)?;
+        let reply = if rules.is_empty() {
+            self.app_state.i18n.get(lang, "rules_empty")
+        } else {
+            let mut lines = vec![self.app_state.i18n.get(lang, "rules_list")];
+            lines.extend(rules.iter().map(|rule| rule.describe()));
+            lines.join("\n")
+        };
+        self.reply(user_id, lang, &reply)
+    }
+
+    pub fn delete_rule(&self, user_id: &str, id: i32) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let key = if AlertRule::delete(id, user.id, &conn)? > 0 {
+            "rule_deleted"
+        } else {
+            "rule_not_found"
+        };
+        let reply = self
+            .app_state
+            .i18n
+            .format(lang, key, &[("id", id.to_string().as_str())]);
+        self.reply(user_id, lang, &reply)
+    }
+
+    fn is_outdated(&self) -> bool {
+        self.last_response
+            .as_ref()
+            .map_or(true, |resp| resp.is_outdated(self.now()))
     }
 
     pub fn inquire_if_needed(&mut self) -> Result<bool, failure::Error> {
-        if self.is_outdated().ok().unwrap_or(true) {
-            self.last_response = self
-                .inquire(LATITUDE, LONGITUDE)
-                .map_err(|e| error!("Error while requesting forecast: {:?}", e.as_fail()))
-                .ok();
-            return Ok(true);
+        if self.is_outdated() {
+            let (lat, lon) = self.app_state.config.default_location();
+            return match self.inquire(lat, lon, i18n::Lang::default()) {
+                Ok(resp) => {
+                    self.last_response = Some(resp);
+                    Ok(true)
+                }
+                Err(e) => {
+                    self.last_response = None;
+                    Err(e)
+                }
+            };
         }
         Ok(false)
     }
@@ -151,11 +431,12 @@ impl WebWorker {
     #[allow(dead_code)]
     fn today(&self) -> Result<&DataPoint, failure::Error> {
         if let Some(ref lr) = self.last_response {
-            let daily = lr.daily.as_ref().ok_or(JsonError::MissingField {
-                name: "daily".to_owned(),
-            })?;
-            let first = daily.data.get(1);
-            return first.ok_or(failure::Error::from(JsonError::ArrayIndex));
+            let today = lr.date_at(self.now());
+            return lr
+                .day(today)
+                .ok_or(failure::Error::from(ForecastError::Missing {
+                    name: format!("daily for {}", today),
+                }));
         }
         Err(failure::Error::from(CustomError {
             msg: "Forecast data is not present.".to_owned(),
@@ -164,7 +445,7 @@ impl WebWorker {
 
     #[allow(dead_code)]
     fn download_images(&self) {
-        let date = chrono::Utc::now();
+        let date = self.now();
         let name = format!("{}-{}-{}.jpg", date.year(), date.month(), date.day());
         self.download_image(name.as_str())
             .map_err(|e| {
@@ -181,130 +462,666 @@ impl WebWorker {
 
     fn tomorrow<'a>(
         &self,
-        forecast: &'a Option<ApiResponse>,
+        forecast: &'a Option<Forecast>,
     ) -> Result<&'a DataPoint, failure::Error> {
         if let Some(ref lr) = forecast {
-            let daily = lr.daily.as_ref().ok_or(JsonError::MissingField {
-                name: "daily".to_owned(),
-            })?;
-            let second = daily.data.get(2);
-            return second.ok_or(failure::Error::from(JsonError::ArrayIndex));
+            return lr
+                .tomorrow(self.now())
+                .ok_or(failure::Error::from(ForecastError::Missing {
+                    name: format!("daily for {}", lr.date_at(self.now()).succ()),
+                }));
         }
         Err(failure::Error::from(CustomError {
             msg: "Forecast data is not present.".to_owned(),
         }))
     }
 
-    pub fn get_user_quota(&self, user_id: &str) -> ServiceQuota {
-        let quota = &self.app_state.quota.read();
-        let user_quota = quota.as_ref().unwrap().get(user_id);
-        match user_quota {
-            Some(ref q) => {
-                (*q).clone()
+    pub fn refresh_subscribers(&self) -> Result<(), failure::Error> {
+        let mut members: Vec<Member> = Vec::new();
+        self.viber.update_subscribers(&mut members)?;
+        let subscribers: Vec<Subscriber> = members
+            .into_iter()
+            .map(|member| Subscriber {
+                viber_id: member.id,
+                name: member.name,
+                avatar: member.avatar,
+                role: member.role,
+            })
+            .collect();
+        let conn = self.app_state.pool.get()?;
+        // another instance may be storing them too
+        self.with_lease("subscribers", || {
+            Subscriber::replace_all(&subscribers, &conn)
+        })
+        .unwrap_or(Ok(()))?;
+        Ok(())
+    }
+
+    pub fn take_quota(&self, user_id: &str, command: &str) -> Result<bool, failure::Error> {
+        let policy = quota::Policy::of(&self.app_state.config);
+        let limits = policy.limits(command);
+        if policy.is_exempt(user_id) || limits.is_empty() {
+            return Ok(true);
+        }
+        let now = self.now().naive_utc();
+        let windows: Vec<(i64, NaiveDateTime)> = limits
+            .iter()
+            .map(|limit| (limit.count, now - limit.window.duration()))
+            .collect();
+        let conn = self.app_state.pool.get()?;
+        Ok(QuotaUsage::take(user_id, command, now, &windows, &conn)?)
+    }
+
+    pub fn show_quota(&self, user_id: &str) -> Result<(), failure::Error> {
+        let lang = self.user_lang(user_id);
+        let catalog = &self.app_state.i18n;
+        let policy = quota::Policy::of(&self.app_state.config);
+        if policy.is_exempt(user_id) {
+            return self.reply(user_id, lang, &catalog.get(lang, "quota_exempt"));
+        }
+        let conn = self.app_state.pool.get()?;
+        let now = self.now().naive_utc();
+        let mut lines = vec![catalog.get(lang, "quota_left")];
+        for command in quota::COMMANDS {
+            let name = catalog.get(lang, quota::label_key(command));
+            for limit in policy.limits(command) {
+                let since = now - limit.window.duration();
+                let left = limit.count - QuotaUsage::count(user_id, command, since, &conn)?;
+                let window = catalog.get(lang, &format!("quota_window_{}", limit.window.code()));
+                lines.push(catalog.format(
+                    lang,
+                    "quota_line",
+                    &[
+                        ("command", name.as_str()),
+                        ("left", left.max(0).to_string().as_str()),
+                        ("limit", limit.count.to_string().as_str()),
+                        ("window", window.as_str()),
+                    ],
+                ));
             }
-            None => {
-                //TODO figure out why this is happening
-                error!("No qouta for user {}", user_id);
-                ServiceQuota {
-                    weather_count: 0,
-                    btc_count: 0
+        }
+        self.reply(user_id, lang, &lines.join("\n"))
+    }
+
+    pub fn link_account(&self, user_id: &str, code: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let now = self.now().naive_utc();
+        let catalog = &self.app_state.i18n;
+        let lang = self.user_lang(user_id);
+        let text = match User::link(code, user_id, now, &conn)? {
+            Some(user) => catalog.format(
+                lang,
+                "account_linked",
+                &[(
+                    "email",
+                    user.email.as_ref().map(|e| e.as_str()).unwrap_or(""),
+                )],
+            ),
+            None => catalog.get(lang, "link_invalid"),
+        };
+        self.reply(user_id, lang, &text)
+    }
+
+    pub fn unblock(&self, user_id: &str, blocked: &str) -> Result<(), failure::Error> {
+        let lang = self.user_lang(user_id);
+        let catalog = &self.app_state.i18n;
+        if user_id != self.viber.admin_id {
+            return self.reply(user_id, lang, &catalog.get(lang, "unknown_command"));
+        }
+        let key = if self.app_state.inbound.unblock(blocked)? {
+            "unblocked"
+        } else {
+            "not_blocked"
+        };
+        self.reply(
+            user_id,
+            lang,
+            &catalog.format(lang, key, &[("user", blocked)]),
+        )
+    }
+
+    fn inquire(&self, lat: f64, lon: f64, lang: i18n::Lang) -> Result<Forecast, failure::Error> {
+        self.app_state.weather.forecast(lat, lon, lang)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.app_state.clock.now()
+    }
+
+    pub fn user_prefs(&self, user_id: &str) -> Prefs {
+        self.app_state
+            .pool
+            .get()
+            .ok()
+            .and_then(|conn| User::by_viber_id(user_id, &conn))
+            .map(|user| Prefs::of(&user))
+            .unwrap_or_default()
+    }
+
+    pub fn user_lang(&self, user_id: &str) -> i18n::Lang {
+        self.user_prefs(user_id).lang
+    }
+
+    pub fn keyboard<'a>(&self, lang: i18n::Lang) -> Keyboard<'a> {
+        common::get_default_keyboard(&self.app_state.i18n, lang)
+    }
+
+    pub fn reply(&self, to: &str, lang: i18n::Lang, text: &str) -> Result<(), failure::Error> {
+        self.viber.send_text_to(text, to, Some(self.keyboard(lang)))
+    }
+
+    pub fn send_btc_price(&self, user_id: &str) -> Result<usize, failure::Error> {
+        let price = self.app_state.usage.track("coindesk", "currentprice", || {
+            bitcoin::get_bitcoin_price().ok_or(failure::Error::from(CustomError {
+                msg: "no price in the response".to_owned(),
+            }))
+        });
+        let lang = self.user_lang(user_id);
+        match price {
+            Ok(price) => {
+                let mut ctx = tera::Context::new();
+                ctx.insert("time", &price.time.updateduk);
+                ctx.insert("rate", &price.bpi.usd.rate);
+                let msg_text = self.app_state.messages.render(lang, "btc", &ctx)?;
+                self.reply(user_id, lang, msg_text.as_str())?;
+                Ok(1)
+            }
+            Err(e) => {
+                if e.downcast_ref::<usage::BudgetExceeded>().is_some() {
+                    self.reply_service_busy(user_id, lang)?;
                 }
+                Err(e)
             }
         }
     }
 
-    pub fn set_user_quota(&self, user_id: &str, user_quota: ServiceQuota) {
-        let mut quota = self.app_state.quota.write();
-        quota
-            .as_mut()
+    fn reply_service_busy(&self, to: &str, lang: i18n::Lang) -> Result<(), failure::Error> {
+        self.reply(to, lang, &self.app_state.i18n.get(lang, "service_busy"))
+    }
+
+    fn send_budget_warnings(&self) -> Result<(), failure::Error> {
+        let admin = &self.viber.admin_id;
+        let lang = self.user_lang(admin);
+        for warning in self.app_state.usage.take_warnings() {
+            let text = self.app_state.i18n.format(
+                lang,
+                "budget_warning",
+                &[
+                    ("provider", warning.provider.as_str()),
+                    ("calls", warning.calls.to_string().as_str()),
+                    ("budget", warning.budget.to_string().as_str()),
+                ],
+            );
+            self.reply(admin, lang, &text)?;
+        }
+        Ok(())
+    }
+
+    fn run_due_jobs(&self) {
+        let now = self.now().timestamp();
+        let due = self.app_state.jobs.write().unwrap().due(now);
+        for name in due {
+            self.with_lease(name, || {
+                if !self.still_due(name, now) {
+                    return;
+                }
+                let success = self.run_recorded(name);
+                let mut jobs = self.app_state.jobs.write().unwrap();
+                if let Some(state) = jobs.finish(name, now, success) {
+                    self.save_job(state);
+                }
+            });
+        }
+    }
+
+    // another instance of the app may have run or paused the job meanwhile
+    fn still_due(&self, name: &str, now: i64) -> bool {
+        let mut jobs = self.app_state.jobs.write().unwrap();
+        self.reload_job(&mut jobs, name);
+        jobs.get(name)
+            .map(|job| !job.state.paused && job.state.next_due <= now)
+            .unwrap_or(false)
+    }
+
+    fn reload_job(&self, jobs: &mut scheduler::Registry, name: &str) {
+        self.app_state
+            .pool
+            .get()
+            .map_err(|e| failure::Error::from(e))
+            .and_then(|conn| {
+                jobs.reload(name, &conn)
+                    .map_err(|e| failure::Error::from(e))
+            })
+            .map_err(|e| error!("Can't reload the state of the {} job. {:?}", name, e))
+            .unwrap_or_default();
+    }
+
+    // takes the lease anew, or renews it if this instance holds it
+    fn take_lease(
+        app_state: &AppStateType,
+        name: &str,
+        instance: &str,
+    ) -> Result<bool, failure::Error> {
+        let now = app_state.clock.now().naive_utc();
+        let expires = now + chrono::Duration::seconds(jobs::LEASE_SECONDS);
+        let conn = app_state.pool.get()?;
+        Ok(JobLease::acquire(name, instance, now, expires, &conn)?)
+    }
+
+    // None if another instance holds the lease, which is renewed while the job runs
+    fn with_lease<T, F>(&self, name: &str, f: F) -> Option<T>
+    where
+        F: FnOnce() -> T,
+    {
+        match WebWorker::take_lease(&self.app_state, name, &self.instance) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!("The {} job is run by another instance.", name);
+                return None;
+            }
+            Err(e) => {
+                error!("Can't take the lease of the {} job. {:?}", name, e);
+                return None;
+            }
+        }
+        let (done, running) = channel::<()>();
+        let renewal = {
+            let (app_state, name, instance) = (
+                self.app_state.clone(),
+                name.to_owned(),
+                self.instance.clone(),
+            );
+            thread::spawn(move || {
+                let every = ::std::time::Duration::from_secs(jobs::LEASE_RENEW_SECONDS);
+                while let Err(RecvTimeoutError::Timeout) = running.recv_timeout(every) {
+                    match WebWorker::take_lease(&app_state, &name, &instance) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            warn!("The lease of the {} job went to another instance.", name)
+                        }
+                        Err(e) => error!("Can't renew the lease of the {} job. {:?}", name, e),
+                    }
+                }
+            })
+        };
+        let result = f();
+        drop(done);
+        renewal.join().unwrap_or_default();
+        self.app_state
+            .pool
+            .get()
+            .map_err(|e| failure::Error::from(e))
+            .and_then(|conn| {
+                JobLease::release(name, &self.instance, &conn).map_err(|e| failure::Error::from(e))
+            })
+            .map_err(|e| error!("Can't release the lease of the {} job. {:?}", name, e))
+            .unwrap_or_default();
+        Some(result)
+    }
+
+    pub fn run_job_now(&self, name: &str) -> Result<(), failure::Error> {
+        let name = self
+            .app_state
+            .jobs
+            .read()
             .unwrap()
-            .insert(user_id.to_owned(), user_quota);
+            .get(name)
+            .map(|job| job.name)
+            .ok_or_else(|| CustomError {
+                msg: format!("no job {}", name),
+            })?;
+        let now = self.now().timestamp();
+        self.with_lease(name, || {
+            let success = self.run_recorded(name);
+            let mut jobs = self.app_state.jobs.write().unwrap();
+            self.reload_job(&mut jobs, name);
+            if let Some(state) = jobs.record(name, now, success) {
+                self.save_job(state);
+            }
+        })
+        .ok_or_else(|| CustomError {
+            msg: format!("the {} job is running on another instance", name),
+        })?;
+        Ok(())
     }
 
-    fn inquire(&self, lat: f64, lon: f64) -> Result<ApiResponse, failure::Error> {
-        let config = &self.app_state.config;
-        let api_key = &config.dark_sky_api_key;
-        let reqwest_client = reqwest::Client::new();
-        let api_client = forecast::ApiClient::new(&reqwest_client);
-        let mut blocks = vec![ExcludeBlock::Alerts];
-
-        let forecast_request =
-            ForecastRequestBuilder::new(api_key.as_ref().unwrap().as_str(), lat, lon)
-                .exclude_block(ExcludeBlock::Hourly)
-                .exclude_blocks(&mut blocks)
-                .extend(ExtendBy::Hourly)
-                .lang(Lang::Ukranian)
-                .units(Units::UK)
-                .build();
-        let mut forecast_response = api_client.get_forecast(forecast_request)?;
-        if !forecast_response.status().is_success() {
-            let mut body = String::new();
-            forecast_response.read_to_string(&mut body)?;
+    pub fn pause_job(&self, name: &str, paused: bool) -> Result<(), failure::Error> {
+        let mut jobs = self.app_state.jobs.write().unwrap();
+        self.reload_job(&mut jobs, name);
+        let state = jobs.set_paused(name, paused).ok_or_else(|| CustomError {
+            msg: format!("no job {}", name),
+        })?;
+        let conn = self.app_state.pool.get()?;
+        state.save(&conn)?;
+        Ok(())
+    }
+
+    fn run_recorded(&self, name: &'static str) -> bool {
+        let started = self.now();
+        let result = self.run_job(name);
+        if let Err(ref e) = result {
+            error!("The {} job failed. {:?}", name, e);
+        }
+        // runs with nothing to do would crowd out the others on the jobs page
+        if let Ok(0) = result {
+            return true;
+        }
+        let run = NewJobRun {
+            job: name,
+            started: started.naive_utc(),
+            finished: self.now().naive_utc(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            messages_sent: *result.as_ref().unwrap_or(&0) as i32,
+        };
+        self.app_state
+            .pool
+            .get()
+            .map_err(|e| failure::Error::from(e))
+            .and_then(|conn| {
+                run.insert(&conn)?;
+                Ok(())
+            })
+            .map_err(|e| error!("Can't record the run of the {} job. {:?}", name, e))
+            .unwrap_or_default();
+        result.is_ok()
+    }
+
+    fn delete_old_job_runs(&self) -> Result<usize, failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let keep = chrono::Duration::days(JOB_RUNS_KEEP_DAYS);
+        JobRun::delete_older(self.now().naive_utc() - keep, &conn)?;
+        Ok(0)
+    }
+
+    fn run_job(&self, name: &str) -> Result<usize, failure::Error> {
+        match name {
+            "forecast_broadcast" => self.send_scheduled_forecasts(),
+            "btc_price" => self.send_btc_price(&self.viber.admin_id),
+            "rain_check" => self.send_rain_alerts(),
+            "rules_check" => self.evaluate_rules(),
+            "cleanup" => self.delete_old_job_runs(),
+            "alerts_check" if self.app_state.weather.has_alerts() => self.send_severe_alerts(),
+            _ => Ok(0),
+        }
+    }
+
+    fn save_job(&self, job: &scheduler::JobState) {
+        self.app_state
+            .pool
+            .get()
+            .map_err(|e| failure::Error::from(e))
+            .and_then(|conn| job.save(&conn).map_err(|e| failure::Error::from(e)))
+            .map_err(|e| error!("Can't save the state of the {} job. {:?}", job.name, e))
+            .unwrap_or_default();
+    }
+
+    // fails if some forecasts couldn't be sent, the rest being delivered anyway
+    fn send_scheduled_forecasts(&self) -> Result<usize, failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let now = self.now();
+        let mut delivered = 0;
+        let mut failed = 0;
+        let mut users = User::with_notify_time(&conn)?;
+        let admin = &self.viber.admin_id;
+        if !users.iter().any(|u| u.viber_id.as_ref() == Some(admin)) {
+            let mut user = User::register_viber(admin, &conn)?;
+            user.notify_time = Some(ADMIN_NOTIFY_TIME);
+            users.push(user);
+        }
+        for user in users {
+            let at = scheduler::minutes_to_time(user.notify_time.unwrap_or_default());
+            let day = match scheduler::daily_delivery_due(&now, &user.tz(), &at, user.last_notified)
+            {
+                Some(day) => day,
+                None => continue,
+            };
+            let viber_id = user.viber_id.as_ref().unwrap();
+            let prefs = Prefs::of(&user);
+            let sent = match user.location() {
+                Some((lat, lon)) => {
+                    let forecast = self.inquire(lat, lon, prefs.lang).ok();
+                    let mut address = self
+                        .get_address_by_location(lat, lon, prefs.lang)
+                        .unwrap_or("".to_owned());
+                    address.push_str("\n");
+                    self.deliver_forecast_for_tomorrow(&forecast, viber_id, &prefs, &address)
+                }
+                None => {
+                    let city = format!("{}\n", self.app_state.config.default_city());
+                    self.with_default_forecast(&prefs, |forecast| {
+                        self.deliver_forecast_for_tomorrow(forecast, viber_id, &prefs, &city)
+                    })
+                }
+            };
+            match sent {
+                Ok(_) => {
+                    User::set_last_notified(user.id, day, &conn)?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    error!("Can't send scheduled forecast to {}. {:?}", viber_id, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
             return Err(failure::Error::from(CustomError {
-                msg: format!("Dark sky response failure: {}", body),
+                msg: format!(
+                    "{} scheduled forecasts were not sent, {} were",
+                    failed, delivered
+                ),
             }));
         }
-        serde_json::from_reader(forecast_response).map_err(|e| failure::Error::from(e))
+        Ok(delivered)
     }
 
-    pub fn send_btc_price(&self, user_id: &str) {
-        let price = bitcoin::get_bitcoin_price();
-        if price.is_some() {
-            let price = price.unwrap();
-            let msg_text = format!(
-                "{} \n1 BTC = {} $",
-                price.time.updateduk, price.bpi.usd.rate
-            );
-            self.viber
-                .send_text_to(
-                    msg_text.as_str(),
-                    &user_id,
-                    Some(common::get_default_keyboard()),
+    pub fn set_notify_time(
+        &self,
+        user_id: &str,
+        minutes: Option<i16>,
+    ) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        User::set_notify_time(user.id, minutes, &conn)?;
+        let lang = user.lang();
+        let text = match minutes {
+            Some(m) => self.app_state.i18n.format(
+                lang,
+                "notify_time_set",
+                &[
+                    (
+                        "time",
+                        scheduler::minutes_to_time(m)
+                            .format("%H:%M")
+                            .to_string()
+                            .as_str(),
+                    ),
+                    ("timezone", user.timezone.as_str()),
+                ],
+            ),
+            None => self.app_state.i18n.get(lang, "notify_time_off"),
+        };
+        self.reply(user_id, lang, &text)
+    }
+
+    pub fn set_timezone(&self, user_id: &str, timezone: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let text = match timezone.parse::<Tz>() {
+            Ok(tz) => {
+                User::set_timezone(user.id, tz.name(), &conn)?;
+                self.app_state
+                    .i18n
+                    .format(lang, "timezone_set", &[("timezone", tz.name())])
+            }
+            Err(_) => {
+                self.app_state
+                    .i18n
+                    .format(lang, "timezone_unknown", &[("timezone", timezone)])
+            }
+        };
+        self.reply(user_id, lang, &text)
+    }
+
+    pub fn set_language(&self, user_id: &str, language: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        match i18n::Lang::from_code(language) {
+            Some(lang) => {
+                User::set_language(user.id, lang, &conn)?;
+                self.reply(
+                    user_id,
+                    lang,
+                    &self.app_state.i18n.get(lang, "language_set"),
                 )
-                .expect("error sending viber message");
-        } else {
-            error!("Could not get bitcoin price.");
+            }
+            None => {
+                let lang = user.lang();
+                self.reply(
+                    user_id,
+                    lang,
+                    &self.app_state.i18n.get(lang, "language_unknown"),
+                )
+            }
         }
     }
 
-    pub fn try_broadcast(&mut self) {
-        {
-            let runner = &mut self.app_state.last_text_broadcast.write().unwrap();
-            //16-20 UTC+2
-            runner.daily(14, 20, &mut || {
-                self.send_forecast_for_tomorrow(&self.last_response, &self.viber.admin_id, "")
-                    .is_ok()
-            });
-        }
-        {
-            let runner = &mut self.app_state.last_btc_update.write().unwrap();
-            runner.daily(3, 6, &mut || {
-                self.send_btc_price(&self.viber.admin_id);
-                true
-            });
+    pub fn set_units(&self, user_id: &str, units: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let text = match UnitSystem::from_code(units) {
+            Some(units) => {
+                User::set_units(user.id, units, &conn)?;
+                self.app_state
+                    .i18n
+                    .format(lang, "units_set", &[("units", units.code())])
+            }
+            None => self.app_state.i18n.get(lang, "units_unknown"),
+        };
+        self.reply(user_id, lang, &text)
+    }
+
+    pub fn set_forecast_fields(&self, user_id: &str, fields: &str) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        let lang = user.lang();
+        let fields = if fields.trim() == "default" {
+            Some(details::DEFAULT_FIELDS.to_vec())
+        } else {
+            details::parse(fields)
+        };
+        let text = match fields {
+            Some(fields) => {
+                let joined = fields.join(",");
+                let stored = if fields == details::DEFAULT_FIELDS {
+                    None
+                } else {
+                    Some(joined.as_str())
+                };
+                User::set_forecast_fields(user.id, stored, &conn)?;
+                self.app_state.i18n.format(
+                    lang,
+                    "fields_set",
+                    &[(
+                        "fields",
+                        if joined.is_empty() {
+                            "-"
+                        } else {
+                            joined.as_str()
+                        },
+                    )],
+                )
+            }
+            None => self.app_state.i18n.format(
+                lang,
+                "fields_unknown",
+                &[("fields", details::FIELDS.join(", ").as_str())],
+            ),
+        };
+        self.reply(user_id, lang, &text)
+    }
+
+    pub fn register_sender(
+        &self,
+        user_id: &str,
+        language: Option<&str>,
+    ) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        User::register_viber_with_language(user_id, language, &conn)?;
+        Ok(())
+    }
+
+    // the cached forecast is in the default language, the others are requested separately
+    fn with_default_forecast<T, F>(&self, prefs: &Prefs, f: F) -> T
+    where
+        F: FnOnce(&Option<Forecast>) -> T,
+    {
+        if prefs.lang == i18n::Lang::default() || !self.app_state.weather.is_localized() {
+            f(&self.last_response)
+        } else {
+            let (lat, lon) = self.app_state.config.default_location();
+            let fresh = self.inquire(lat, lon, prefs.lang).ok();
+            f(&fresh)
         }
     }
 
+    pub fn default_forecast_for_tomorrow(&self, user_id: &str) -> Result<(), failure::Error> {
+        let prefs = self.user_prefs(user_id);
+        self.with_default_forecast(&prefs, |forecast| {
+            let city = format!("{}\n", self.app_state.config.default_city());
+            self.send_forecast_for_tomorrow(forecast, user_id, &prefs, &city)
+        })
+    }
+
     pub fn immediate_forecast_for_tomorrow(
         &self,
         user_id: &str,
         lat: f64,
         lon: f64,
     ) -> Result<(), failure::Error> {
-        let forecast = self.inquire(lat, lon).ok();
+        let prefs = self.user_prefs(user_id);
+        self.save_location(user_id, lat, lon)
+            .map_err(|e| error!("Can't save location of {}. {:?}", user_id, e))
+            .unwrap_or_default();
+        let forecast = self.inquire(lat, lon, prefs.lang).ok();
         let mut address = self
-            .get_address_by_location(lat, lon)
+            .get_address_by_location(lat, lon, prefs.lang)
             .unwrap_or("".to_owned());
         address.push_str("\n");
-        self.send_forecast_for_tomorrow(&forecast, user_id, &address)
+        self.send_forecast_for_tomorrow(&forecast, user_id, &prefs, &address)
     }
 
-    fn get_address_by_location(&self, lat: f64, lon: f64) -> Result<String, failure::Error> {
-        let key = self.app_state.config.google_maps_api_key.as_ref().unwrap();
-        let query = format!("https://maps.googleapis.com/maps/api/geocode/json?latlng={},{}&language=uk&key={}&result_type=political|country|administrative_area_level_1|administrative_area_level_2", lat, lon, key);
-        let reqwest_client = reqwest::Client::new();
-        let response = reqwest_client.get(&query).send()?;
-        let geocoding: ReverseGeocoding = serde_json::from_reader(response)?;
+    fn save_location(&self, user_id: &str, lat: f64, lon: f64) -> Result<(), failure::Error> {
+        let conn = self.app_state.pool.get()?;
+        let user = User::register_viber(user_id, &conn)?;
+        User::set_location(user.id, lat, lon, &conn)?;
+        Ok(())
+    }
+
+    fn get_address_by_location(
+        &self,
+        lat: f64,
+        lon: f64,
+        lang: i18n::Lang,
+    ) -> Result<String, failure::Error> {
+        let key = self
+            .app_state
+            .config
+            .google_maps_api_key
+            .as_ref()
+            .ok_or_else(|| CustomError {
+                msg: "google_maps_api_key is not set".to_owned(),
+            })?;
+        let query = format!("https://maps.googleapis.com/maps/api/geocode/json?latlng={},{}&language={}&key={}&result_type=political|country|administrative_area_level_1|administrative_area_level_2", lat, lon, lang.code(), key);
+        let geocoding: ReverseGeocoding =
+            self.app_state.usage.track("google_maps", "geocode", || {
+                let reqwest_client = reqwest::Client::new();
+                let response = reqwest_client.get(&query).send()?;
+                Ok(serde_json::from_reader(response)?)
+            })?;
         let geo = geocoding.results.first();
         if geo.is_none() {
             return Ok(format!("lat: {}, lon: {}", lat, lon));
@@ -314,7 +1131,7 @@ impl WebWorker {
 
     pub fn send_image(&self) -> Result<(), failure::Error> {
         use std::path;
-        let date = Utc::now();
+        let date = self.now();
         let name = format!("{}-{}-{}.jpg", date.year(), date.month(), date.day());
         let thumb = format!("{}-{}-{}t.jpg", date.year(), date.month(), date.day());
         let file_path = format!("static/{}", &name);
@@ -330,10 +1147,11 @@ impl WebWorker {
                 self.app_state.config.hosting_root_url.clone().unwrap(),
                 &thumb
             );
+            let lang = self.user_lang(&self.viber.admin_id);
             self.viber.send_picture_message_to_admin(
                 url.as_str(),
                 thumb_url.as_str(),
-                "Прогноз на 7 дней",
+                &self.app_state.i18n.get(lang, "forecast_image"),
             )
         } else {
             Err((CustomError {
@@ -343,64 +1161,163 @@ impl WebWorker {
         }
     }
 
-    pub fn format_forecast(data_point: &DataPoint) -> Result<String, failure::Error> {
-        let dt = Utc.timestamp(data_point.time as i64, 0);
-        let (precip, probability) = match data_point.precip_type.as_ref() {
-            Some(p) => {
-                let pr = match p {
-                    PrecipType::Rain => "Дожщ",
-                    PrecipType::Snow => "Сніг",
-                    PrecipType::Sleet => "Дожщ зі снігом",
-                };
-                (pr, data_point.precip_probability.unwrap())
-            }
-            None => ("-", 0.0),
-        };
-
-        let precip_formatted = if probability < 0.01 {
-            "Без опадів".to_owned()
-        } else {
-            format!(
-                " \nОпади: {:?} з ймовірністю {:.2}%",
-                precip,
-                probability * 100.0
-            )
+    pub fn format_forecast(
+        &self,
+        prefs: &Prefs,
+        tz: &Tz,
+        data_point: &DataPoint,
+        today: Option<&DataPoint>,
+    ) -> Result<String, failure::Error> {
+        let catalog = &self.app_state.i18n;
+        let config = &self.app_state.config;
+        let lang = prefs.lang;
+        let dt = tz.timestamp(data_point.time as i64, 0);
+        let low = data_point.temperature_low.ok_or(ForecastError::Missing {
+            name: "temperature_low".to_owned(),
+        })?;
+        let high = data_point.temperature_high.ok_or(ForecastError::Missing {
+            name: "temperature_high".to_owned(),
+        })?;
+        let probability = data_point.precip_probability.unwrap_or_default();
+        let precip = match data_point.precip_type.as_ref() {
+            Some(p) if probability >= 0.01 => Some(catalog.get(lang, format::precip_key(p))),
+            _ => None,
         };
-        Ok(format!("Прогноз на завтра {}.{}:\n{}\nТемпература: від {:?}\u{2103} до {:?}\u{2103}\n{}", dt.day(),
-                              dt.month(),
-                data_point.summary.clone().unwrap_or_default(),
-                data_point.temperature_low.ok_or(
-                                  JsonError::MissingField { name: "temperature_low".to_owned() }
-                              )?,
-                data_point.temperature_high.ok_or(
-                                  JsonError::MissingField { name: "temperature_high".to_owned() }
-                              )?, &precip_formatted))
+        let mut ctx = tera::Context::new();
+        ctx.insert("date", &format!("{}.{}", dt.day(), dt.month()));
+        ctx.insert(
+            "summary",
+            &format::summary(catalog, prefs, data_point).unwrap_or_default(),
+        );
+        ctx.insert(
+            "low",
+            &format!("{:.1}", weather::UNITS.temperature(low, prefs.units)),
+        );
+        ctx.insert(
+            "high",
+            &format!("{:.1}", weather::UNITS.temperature(high, prefs.units)),
+        );
+        ctx.insert("unit", prefs.units.temperature_symbol());
+        ctx.insert("precip", &precip);
+        ctx.insert("probability", &format!("{:.0}", probability * 100.0));
+        ctx.insert("details", &details::lines(catalog, prefs, tz, data_point));
+        ctx.insert(
+            "advice",
+            &advice::lines(catalog, &advice::Thresholds::of(config), prefs, data_point),
+        );
+        ctx.insert(
+            "comparison",
+            &today.and_then(|today| {
+                compare::line(
+                    catalog,
+                    &compare::Thresholds::of(config),
+                    prefs,
+                    today,
+                    data_point,
+                )
+            }),
+        );
+        self.app_state.messages.render(lang, "forecast", &ctx)
     }
 
     pub fn send_forecast_for_tomorrow(
         &self,
-        forecast: &Option<ApiResponse>,
+        forecast: &Option<Forecast>,
         to: &str,
+        prefs: &Prefs,
         additional_text: &str,
     ) -> Result<(), failure::Error> {
-        let mut quota = self.get_user_quota(to);
-        if quota.weather_count == 0 {
-            return self.viber.send_text_to(
-                "Max request count exceeded.",
-                to,
-                Some(get_default_keyboard()),
-            );
+        if forecast.is_none() {
+            return self.reply_service_busy(to, prefs.lang);
+        }
+        if !self.take_weather_quota(to, "forecast", prefs.lang)? {
+            return Ok(());
+        }
+        self.deliver_forecast_for_tomorrow(forecast, to, prefs, additional_text)
+    }
+
+    fn take_weather_quota(
+        &self,
+        to: &str,
+        command: &str,
+        lang: i18n::Lang,
+    ) -> Result<bool, failure::Error> {
+        if !self.take_quota(to, command)? {
+            self.reply(to, lang, &self.app_state.i18n.get(lang, "quota_exceeded"))?;
+            return Ok(false);
         }
-        quota.weather_count -= 1;
-        self.set_user_quota(to, quota);
-        use common::get_default_keyboard;
+        Ok(true)
+    }
+
+    pub fn default_forecast(
+        &self,
+        user_id: &str,
+        kind: ForecastKind,
+    ) -> Result<(), failure::Error> {
+        let prefs = self.user_prefs(user_id);
+        if !self.take_weather_quota(user_id, quota::command_of(kind), prefs.lang)? {
+            return Ok(());
+        }
+        let msg = self.with_default_forecast(&prefs, |forecast| match forecast {
+            Some(_) => self.format_forecast_kind(kind, forecast, &prefs),
+            None => Ok(self.app_state.i18n.get(prefs.lang, "service_busy")),
+        })?;
+        self.reply(user_id, prefs.lang, &msg)
+    }
+
+    fn format_forecast_kind(
+        &self,
+        kind: ForecastKind,
+        forecast: &Option<Forecast>,
+        prefs: &Prefs,
+    ) -> Result<String, failure::Error> {
+        let resp = forecast.as_ref().ok_or(CustomError {
+            msg: "Forecast data is not present.".to_owned(),
+        })?;
+        format::format_kind(&self.app_state.i18n, prefs, kind, resp, self.now())
+    }
+
+    fn deliver_forecast_for_tomorrow(
+        &self,
+        forecast: &Option<Forecast>,
+        to: &str,
+        prefs: &Prefs,
+        additional_text: &str,
+    ) -> Result<(), failure::Error> {
         let day = self.tomorrow(forecast)?;
-        let msg = WebWorker::format_forecast(day)?;
-        self.viber.send_text_to(
-            &(additional_text.to_owned() + msg.as_str()),
-            to,
-            Some(get_default_keyboard()),
-        )?;
+        let tz = forecast
+            .as_ref()
+            .map(|f| f.tz())
+            .unwrap_or(chrono_tz::Europe::Kiev);
+        let today = forecast
+            .as_ref()
+            .and_then(|f| compare::today(f, self.now()));
+        let msg = self.format_forecast(prefs, &tz, day, today.as_ref())?;
+        if self.app_state.config.forecast_pictures.unwrap_or(false) {
+            self.send_conditions_picture(to, prefs, day)
+                .map_err(|e| error!("Can't send the picture of the conditions. {:?}", e))
+                .unwrap_or_default();
+        }
+        self.reply(to, prefs.lang, &(additional_text.to_owned() + msg.as_str()))?;
         Ok(())
     }
+
+    fn send_conditions_picture(
+        &self,
+        to: &str,
+        prefs: &Prefs,
+        data_point: &DataPoint,
+    ) -> Result<(), failure::Error> {
+        let icon = match data_point.icon {
+            Some(icon) => icon,
+            None => return Ok(()),
+        };
+        let url = format!(
+            "{}api/static/{}",
+            self.app_state.config.hosting_root_url.clone().unwrap(),
+            icon.image()
+        );
+        let text = format::summary(&self.app_state.i18n, prefs, data_point).unwrap_or_default();
+        self.viber.send_picture_message_to(&url, &url, &text, to)
+    }
 }
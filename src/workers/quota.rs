@@ -0,0 +1,196 @@
+use chrono::Duration;
+use common::messages::ForecastKind;
+use config::Config;
+
+// "forecast" stands for the forecast for tomorrow, for the default city or a sent location
+pub static COMMANDS: &[&str] = &["forecast", "now", "today", "hourly", "week", "bitcoin"];
+
+static WEATHER_PER_DAY: i64 = 22;
+static BITCOIN_PER_DAY: i64 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Hour,
+    Day,
+}
+
+impl Window {
+    pub fn from_code(code: &str) -> Option<Window> {
+        match code.trim() {
+            "hour" => Some(Window::Hour),
+            "day" => Some(Window::Day),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Window::Hour => "hour",
+            Window::Day => "day",
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Window::Hour => Duration::hours(1),
+            Window::Day => Duration::days(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub count: i64,
+    pub window: Window,
+}
+
+pub fn parse_limits(text: &str) -> Option<Vec<Limit>> {
+    if text.trim() == "unlimited" {
+        return Some(Vec::new());
+    }
+    text.split(',')
+        .map(|limit| {
+            let mut parts = limit.splitn(2, '/');
+            let count = parts.next()?.trim().parse().ok().filter(|c| *c >= 0)?;
+            let window = Window::from_code(parts.next()?)?;
+            Some(Limit { count, window })
+        })
+        .collect()
+}
+
+pub fn command_of(kind: ForecastKind) -> &'static str {
+    match kind {
+        ForecastKind::Now => "now",
+        ForecastKind::Today => "today",
+        ForecastKind::Hourly => "hourly",
+        ForecastKind::Week => "week",
+    }
+}
+
+pub fn label_key(command: &str) -> &'static str {
+    match command {
+        "now" => "keyboard_now",
+        "today" => "keyboard_today",
+        "hourly" => "keyboard_hourly",
+        "week" => "keyboard_week",
+        "bitcoin" => "keyboard_bitcoin",
+        _ => "keyboard_tomorrow",
+    }
+}
+
+pub struct Policy<'a> {
+    config: &'a Config,
+}
+
+impl<'a> Policy<'a> {
+    pub fn of(config: &'a Config) -> Policy<'a> {
+        Policy { config }
+    }
+
+    pub fn limits(&self, command: &str) -> Vec<Limit> {
+        if let Some(text) = self
+            .config
+            .quota_limits
+            .as_ref()
+            .and_then(|l| l.get(command))
+        {
+            match parse_limits(text) {
+                Some(limits) => return limits,
+                None => error!("Invalid quota of the {} command: {}", command, text),
+            }
+        }
+        let count = if command == "bitcoin" {
+            BITCOIN_PER_DAY
+        } else {
+            WEATHER_PER_DAY
+        };
+        vec![Limit {
+            count,
+            window: Window::Day,
+        }]
+    }
+
+    pub fn is_exempt(&self, viber_id: &str) -> bool {
+        self.config.admin_id.as_ref().map(|a| a.as_str()) == Some(viber_id)
+            || self
+                .config
+                .quota_exempt
+                .as_ref()
+                .map_or(false, |ids| ids.iter().any(|id| id == viber_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(count: i64, window: Window) -> Limit {
+        Limit { count, window }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_limits("5/hour,20/day"),
+            Some(vec![limit(5, Window::Hour), limit(20, Window::Day)])
+        );
+        assert_eq!(
+            parse_limits(" 5 / hour , 20/ day "),
+            Some(vec![limit(5, Window::Hour), limit(20, Window::Day)])
+        );
+        assert_eq!(parse_limits("0/day"), Some(vec![limit(0, Window::Day)]));
+        assert_eq!(parse_limits("unlimited"), Some(Vec::new()));
+        assert_eq!(parse_limits(" unlimited "), Some(Vec::new()));
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for text in &[
+            "",
+            "-1/day",
+            "5/week",
+            "5/Hour",
+            "5",
+            "/day",
+            "five/day",
+            "5/hour,",
+            "5/hour,oops",
+            "unlimited,5/day",
+        ] {
+            assert_eq!(parse_limits(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn limits_of_the_config() {
+        let config: Config = ::serde_json::from_str(
+            r#"{"quota_limits": {"now": "5/hour,20/day", "week": "5/fortnight", "bitcoin": "unlimited"}}"#,
+        )
+        .unwrap();
+        let policy = Policy::of(&config);
+        assert_eq!(
+            policy.limits("now"),
+            vec![limit(5, Window::Hour), limit(20, Window::Day)]
+        );
+        assert!(policy.limits("bitcoin").is_empty());
+        // an invalid quota falls back to the default one
+        assert_eq!(
+            policy.limits("week"),
+            vec![limit(WEATHER_PER_DAY, Window::Day)]
+        );
+        assert_eq!(
+            policy.limits("today"),
+            vec![limit(WEATHER_PER_DAY, Window::Day)]
+        );
+        let config: Config = ::serde_json::from_str("{}").unwrap();
+        let policy = Policy::of(&config);
+        assert_eq!(
+            policy.limits("bitcoin"),
+            vec![limit(BITCOIN_PER_DAY, Window::Day)]
+        );
+        assert_eq!(
+            policy.limits("forecast"),
+            vec![limit(WEATHER_PER_DAY, Window::Day)]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use weather::{DataPoint, Forecast};
+
+static HORIZON: i64 = 60 * 60;
+// mm/h, counted as rain where the provider gives no probability
+static MIN_INTENSITY: f64 = 0.2;
+
+fn is_likely(data_point: &DataPoint, threshold: f64) -> bool {
+    match data_point.precip_probability {
+        Some(probability) => probability >= threshold,
+        None => data_point.precip_intensity.unwrap_or_default() >= MIN_INTENSITY,
+    }
+}
+
+fn first_likely(points: &[DataPoint], now: i64, threshold: f64) -> Option<i64> {
+    points
+        .iter()
+        .filter(|dp| dp.time as i64 >= now && (dp.time as i64) < now + HORIZON)
+        .find(|dp| is_likely(dp, threshold))
+        .map(|dp| dp.time as i64 - now)
+}
+
+// minutely data is preferred, hourly where the provider has none; None when it is already raining
+pub fn seconds_until_rain(resp: &Forecast, now: i64, threshold: f64) -> Option<i64> {
+    if resp
+        .current
+        .as_ref()
+        .map(|dp| is_likely(dp, threshold))
+        .unwrap_or(false)
+    {
+        return None;
+    }
+    if resp.minutely.is_empty() {
+        first_likely(&resp.hourly, now, threshold)
+    } else {
+        first_likely(&resp.minutely, now, threshold)
+    }
+}
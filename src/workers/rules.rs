@@ -0,0 +1,175 @@
+use units::UnitSystem;
+use weather::DataPoint;
+
+pub static FIELDS: &[&str] = &[
+    "temperature_low",
+    "temperature_high",
+    "apparent_temperature_low",
+    "apparent_temperature_high",
+    "dew_point",
+    "wind_speed",
+    "wind_gust",
+    "uv_index",
+    "humidity",
+    "cloud_cover",
+    "precip_probability",
+    "precip_intensity",
+    "pressure",
+    "visibility",
+];
+
+pub static OPERATORS: &[&str] = &["<", "<=", ">", ">="];
+
+// ratios are shown to users in percents
+fn percent(value: Option<f64>) -> Option<f64> {
+    value.map(|v| v * 100.0)
+}
+
+pub fn value(field: &str, dp: &DataPoint, from: UnitSystem, to: UnitSystem) -> Option<f64> {
+    let temperature = |v: Option<f64>| v.map(|v| from.temperature(v, to));
+    let speed = |v: Option<f64>| v.map(|v| from.speed(v, to));
+    match field {
+        "temperature_low" => temperature(dp.temperature_low),
+        "temperature_high" => temperature(dp.temperature_high),
+        "apparent_temperature_low" => temperature(dp.apparent_temperature_low),
+        "apparent_temperature_high" => temperature(dp.apparent_temperature_high),
+        "dew_point" => temperature(dp.dew_point),
+        "wind_speed" => speed(dp.wind_speed),
+        "wind_gust" => speed(dp.wind_gust),
+        "uv_index" => dp.uv_index,
+        "humidity" => percent(dp.humidity),
+        "cloud_cover" => percent(dp.cloud_cover),
+        "precip_probability" => percent(dp.precip_probability),
+        "precip_intensity" => dp.precip_intensity.map(|v| from.precip(v, to)),
+        "pressure" => dp.pressure,
+        "visibility" => dp.visibility.map(|v| from.distance(v, to)),
+        _ => None,
+    }
+}
+
+pub fn unit(field: &str, units: UnitSystem) -> &'static str {
+    match field {
+        "temperature_low"
+        | "temperature_high"
+        | "apparent_temperature_low"
+        | "apparent_temperature_high"
+        | "dew_point" => units.temperature_symbol(),
+        "wind_speed" | "wind_gust" => units.speed_symbol(),
+        "humidity" | "cloud_cover" | "precip_probability" => "%",
+        "precip_intensity" => units.precip_symbol(),
+        "pressure" => "hPa",
+        "visibility" => units.distance_symbol(),
+        _ => "",
+    }
+}
+
+pub fn matches(op: &str, actual: f64, threshold: f64) -> bool {
+    match op {
+        "<" => actual < threshold,
+        "<=" => actual <= threshold,
+        ">" => actual > threshold,
+        ">=" => actual >= threshold,
+        _ => false,
+    }
+}
+
+pub fn parse(text: &str) -> Option<(String, String, f64)> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    if parts.len() != 3 || !FIELDS.contains(&parts[0]) || !OPERATORS.contains(&parts[1]) {
+        return None;
+    }
+    let threshold = parts[2].replace(',', ".").parse().ok()?;
+    Some((parts[0].to_owned(), parts[1].to_owned(), threshold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_each_operator() {
+        for op in OPERATORS {
+            let text = format!("temperature_low {} -2.5", op);
+            assert_eq!(
+                parse(&text),
+                Some(("temperature_low".to_owned(), op.to_string(), -2.5))
+            );
+        }
+        assert_eq!(
+            parse("  wind_speed   >=  10,5 "),
+            Some(("wind_speed".to_owned(), ">=".to_owned(), 10.5))
+        );
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for text in &[
+            "",
+            "temperature_low <",
+            "temperature_low < 0 1",
+            "temperature < 0",
+            "temperature_low = 0",
+            "temperature_low << 0",
+            "temperature_low < zero",
+            "temperature_low <0",
+        ] {
+            assert_eq!(parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn matches_each_operator() {
+        assert!(matches("<", -1.0, 0.0));
+        assert!(!matches("<", 0.0, 0.0));
+        assert!(matches("<=", 0.0, 0.0));
+        assert!(!matches("<=", 0.1, 0.0));
+        assert!(matches(">", 0.1, 0.0));
+        assert!(!matches(">", 0.0, 0.0));
+        assert!(matches(">=", 0.0, 0.0));
+        assert!(!matches(">=", -0.1, 0.0));
+        assert!(!matches("=", 0.0, 0.0));
+    }
+
+    #[test]
+    fn value_in_the_units() {
+        let dp = DataPoint {
+            temperature_low: Some(0.0),
+            wind_speed: Some(10.0),
+            humidity: Some(0.65),
+            precip_intensity: Some(25.4),
+            visibility: Some(16.093_44),
+            pressure: Some(1013.0),
+            ..DataPoint::default()
+        };
+        let si = UnitSystem::Si;
+        let us = UnitSystem::Us;
+        let close = |field, to, expected: f64| {
+            let actual = value(field, &dp, si, to).unwrap();
+            assert!((actual - expected).abs() < 0.01, "{} {}", field, actual);
+        };
+        close("temperature_low", si, 0.0);
+        close("temperature_low", us, 32.0);
+        close("wind_speed", us, 22.37);
+        close("wind_speed", UnitSystem::Ca, 36.0);
+        close("humidity", us, 65.0);
+        close("precip_intensity", us, 1.0);
+        close("visibility", us, 10.0);
+        close("visibility", UnitSystem::Uk, 10.0);
+        close("pressure", us, 1013.0);
+        assert_eq!(value("dew_point", &dp, si, us), None);
+        assert_eq!(value("unknown", &dp, si, si), None);
+    }
+
+    #[test]
+    fn every_field_has_a_unit() {
+        for field in FIELDS {
+            if *field != "uv_index" {
+                assert_ne!(unit(field, UnitSystem::Si), "", "{}", field);
+            }
+        }
+        assert_eq!(unit("temperature_high", UnitSystem::Us), "\u{2109}");
+        assert_eq!(unit("wind_gust", UnitSystem::Ca), "km/h");
+        assert_eq!(unit("visibility", UnitSystem::Uk), "mi");
+        assert_eq!(unit("unknown", UnitSystem::Si), "");
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::*;
+use chrono_tz::Tz;
+use i18n::{Catalog, Templates};
+use weather::Alert;
+use workers::Prefs;
+
+pub fn format(
+    alert: &Alert,
+    catalog: &Catalog,
+    templates: &Templates,
+    prefs: &Prefs,
+    tz: &Tz,
+) -> Result<String, failure::Error> {
+    let severity = alert
+        .severity
+        .as_ref()
+        .map(|s| catalog.get(prefs.lang, &format!("severity_{}", s)))
+        .unwrap_or_else(|| catalog.get(prefs.lang, "severity_advisory"));
+    let mut ctx = tera::Context::new();
+    ctx.insert("severity", &severity);
+    let title = alert
+        .kind
+        .as_ref()
+        .map(|k| catalog.get(prefs.lang, &format!("alert_{}", k)))
+        .unwrap_or_else(|| alert.title.clone());
+    ctx.insert("title", &title);
+    ctx.insert("description", &alert.description);
+    ctx.insert(
+        "from",
+        &tz.timestamp(alert.time as i64, 0)
+            .format("%d.%m %H:%M")
+            .to_string(),
+    );
+    ctx.insert(
+        "to",
+        &tz.timestamp(alert.expires() as i64, 0)
+            .format("%d.%m %H:%M")
+            .to_string(),
+    );
+    templates.render(prefs.lang, "alert", &ctx)
+}